@@ -0,0 +1,12 @@
+use message_obfuscation::compressor::bwt::{bwt_decode, bwt_encode};
+
+fn main() {
+    let message = "LET HIM COOK";
+    let (transformed_text, index) = bwt_encode(message);
+    let decoded = bwt_decode(&transformed_text, index);
+    
+    println!("Message: {}", message);
+    println!("Transformed Text: {}", transformed_text);
+    println!("Index: {}", index);
+    println!("Decoded Text: {}", decoded);
+}
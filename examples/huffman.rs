@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use message_obfuscation::compressor::huffman::{
+    build_canonical_codebook, build_codebook, build_huffman_tree, canon_length, canonical_decode_bits,
+    canonical_encode_bits, codebook_entries, decode_huffman, encode_huffman, entries_to_dot, entries_to_json,
+    tree_entries,
+};
+
+fn main() {
+    let message: Vec<u8> = vec![5, 5, 6, 3, 3, 3, 1, 6, 6];
+    println!("Original Message: {:?}", message);
+
+    let huffman_tree = build_huffman_tree(&message);
+
+    let mut codeword_map: HashMap<u8, String> = HashMap::new();
+    build_codebook(&huffman_tree, &mut String::new(), &mut codeword_map);
+    //println!("{:?}", huffman_tree);
+    println!("Original Codebook: {:?}", codeword_map);
+
+    let canonical_codebook = build_canonical_codebook(&codeword_map);
+    println!("Canonical Codebook: {:?}", canonical_codebook);
+
+    let encoded_bits = encode_huffman(&message, &codeword_map);
+    println!("Encoded Bits: {:?}", encoded_bits);
+
+    let canonical_encoded_bits = canonical_encode_bits(&message, &canonical_codebook);
+    println!("Canonical Encoded Bits: {:?}", canonical_encoded_bits);
+
+    let canon_length = canon_length(&canonical_codebook);
+    println!("Canon Length: {:?}", canon_length);
+
+    let decoded_message = decode_huffman(&encoded_bits, &codeword_map);
+    println!("Decoded Message (Huffman only): {:?}", decoded_message); 
+
+    let c_decoded_message = canonical_decode_bits(&canonical_encoded_bits, &canonical_codebook);
+    println!("Decoded Message (Canonical): {:?}", c_decoded_message);
+
+    // Pipe the DOT output into `dot -Tsvg` to draw the trees
+    println!("Huffman Tree (DOT):\n{}", entries_to_dot(&tree_entries(&huffman_tree)));
+    let mut freqs = [0u32; 256];
+    message.iter().for_each(|&value| freqs[value as usize] += 1);
+    let canonical_entries = codebook_entries(&canonical_codebook, &freqs);
+    println!("Canonical Tree (DOT):\n{}", entries_to_dot(&canonical_entries));
+    println!("Canonical Codebook (JSON):\n{}", entries_to_json(&canonical_entries));
+}
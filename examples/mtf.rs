@@ -0,0 +1,24 @@
+use std::io;
+
+use message_obfuscation::compressor::mtf::{mtf_decode, mtf_encode};
+
+fn main() {
+    let mut input_text = String::new();
+    let mut alphabet = String::new();
+
+    println!("Enter the input text: ");
+    io::stdin().read_line(&mut input_text).expect("Failed to read input.");
+    let input_text = input_text.trim();
+
+    println!("Enter the alphabet: ");
+    io::stdin().read_line(&mut alphabet).expect("Failed to read input.");
+    let alphabet = alphabet.trim();
+
+    let encoded_text = match mtf_encode(input_text, alphabet) {
+        Ok(encoded_text) => encoded_text,
+        Err(e) => return println!("Error: {}", e),
+    };
+    println!("S: {:?}", encoded_text);
+    let decoded_text = mtf_decode(&encoded_text, alphabet).expect("the encoder only emits indices into the alphabet");
+    println!("M: {}", decoded_text);
+}
@@ -0,0 +1,58 @@
+// Times the Vec<char> move-to-front against the array-based byte version and checks they agree.
+// Run with `cargo run --release --example mtf_bench`.
+
+use std::time::{Duration, Instant};
+
+use message_obfuscation::compressor::bwt::bwt_encode_bytes;
+use message_obfuscation::compressor::mtf::{byte_alphabet, mtf_decode, mtf_decode_bytes, mtf_encode, mtf_encode_bytes};
+use message_obfuscation::diropql::zip::write_diropql_bytes;
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn compare(name: &str, data: &[u8], alphabet: &[u8]) {
+    let (text, alphabet_text) = (latin1(data), latin1(alphabet));
+
+    let (old, old_encode) = timed(|| mtf_encode(&text, &alphabet_text).unwrap());
+    let (new, new_encode) = timed(|| mtf_encode_bytes(data, alphabet).unwrap());
+    assert_eq!(old, new, "{}: outputs differ", name);
+
+    let (old_decoded, old_decode) = timed(|| mtf_decode(&old, &alphabet_text).unwrap());
+    let (new_decoded, new_decode) = timed(|| mtf_decode_bytes(&new, alphabet).unwrap());
+    assert_eq!(old_decoded, text);
+    assert_eq!(new_decoded, data);
+
+    println!("{} ({} bytes, {} symbols)", name, data.len(), alphabet.len());
+    for (step, old, new) in [("encode", old_encode, new_encode), ("decode", old_decode, new_decode)] {
+        println!(
+            "  {}: {:>9.2}ms -> {:>7.2}ms  ({:.1}x)",
+            step,
+            old.as_secs_f64() * 1000.0,
+            new.as_secs_f64() * 1000.0,
+            old.as_secs_f64() / new.as_secs_f64()
+        );
+    }
+}
+
+fn main() {
+    let message = include_bytes!("../README.md").repeat(4);
+    let program = write_diropql_bytes(&message).into_bytes();
+    let (transformed, _) = bwt_encode_bytes(&program);
+    compare("BWT of a diropql program", &transformed, b"\0diropql");
+
+    let mut state = 1u32;
+    let noise: Vec<u8> = (0..1 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    compare("random bytes", &noise, &byte_alphabet());
+}
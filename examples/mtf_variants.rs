@@ -0,0 +1,69 @@
+// Compares the symbol-ranking transforms on the BWT of a diropql program.
+// Run with `cargo run --release --example mtf_variants [FILE]`, FILE defaults to README.md.
+
+use std::time::Instant;
+
+use message_obfuscation::compressor::bwt::bwt_encode_bytes;
+use message_obfuscation::compressor::mtf::{
+    input_alphabet, inversion_frequencies_encode, rank_encode, MtfVariant,
+};
+use message_obfuscation::diropql::zip::{write_diropql_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::{Bwt, Diropql, Huffman, Mtf, MtfAlphabet, Pipeline, Rle};
+
+// Order-0 entropy in bits per symbol
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    data.iter().for_each(|&b| counts[b as usize] += 1);
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / data.len() as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn main() {
+    let path = std::env::args().nth(1).unwrap_or_else(|| "README.md".to_string());
+    let message = std::fs::read(&path).expect("cannot read the input file");
+
+    let program = write_diropql_bytes(&message).into_bytes();
+    let (transformed, _) = bwt_encode_bytes(&program);
+    let alphabet = input_alphabet(&transformed);
+
+    println!("{}: {} bytes, diropql program {} bytes", path, message.len(), program.len());
+    println!("{:<5} {:>9} {:>8} {:>9} {:>10} {:>11}", "", "time", "zeros", "bits/sym", "order-0", "blob");
+
+    for variant in MtfVariant::ALL {
+        let start = Instant::now();
+        let ranked = match variant {
+            MtfVariant::InversionFrequencies => inversion_frequencies_encode(&transformed),
+            _ => rank_encode(&transformed, &alphabet, variant).unwrap(),
+        };
+        let elapsed = start.elapsed();
+
+        let zeros = ranked.iter().filter(|&&b| b == 0).count() as f64 / ranked.len().max(1) as f64;
+        let bits = entropy(&ranked);
+
+        // The whole container, with the transform followed by the zero-run coder and Huffman
+        let pipeline = Pipeline::builder()
+            .stage(Diropql)
+            .stage(Bwt { block_size: program.len() + 1, ..Bwt::default() })
+            .stage(Mtf { alphabet: MtfAlphabet::Input, variant })
+            .stage(Rle)
+            .stage(Huffman::default())
+            .build();
+        let blob = write_diropqlz_with(&message, &pipeline).unwrap();
+
+        println!(
+            "{:<5} {:>7.2}ms {:>7.1}% {:>9.3} {:>9}B {:>10}B",
+            variant.name(),
+            elapsed.as_secs_f64() * 1000.0,
+            zeros * 100.0,
+            bits,
+            (bits * ranked.len() as f64 / 8.0).ceil(),
+            blob.len()
+        );
+    }
+}
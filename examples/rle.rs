@@ -0,0 +1,23 @@
+use std::io;
+
+use message_obfuscation::compressor::rle::{rle_decode, rle_encode};
+
+fn main() {
+    let mut input = String::new();
+    println!("Enter a vector (e.g., [3, 3, 4, 1, 1, 1, 0, 0, 4, 4]): ");
+    io::stdin().read_line(&mut input).expect("Failed to read input.");
+
+    let sequence: Vec<u8> = input
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(|s| s.trim().parse().expect("Invalid number"))
+        .collect();
+
+    let encoded_sequence = rle_encode(&sequence);
+    println!("Encoded sequence: {:?}", encoded_sequence);
+
+    let decoded_sequence = rle_decode(&encoded_sequence).expect("Invalid RLE sequence");
+    println!("Decoded sequence: {:?}", decoded_sequence);
+}
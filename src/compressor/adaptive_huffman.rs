@@ -0,0 +1,147 @@
+// One-pass adaptive Huffman coding (FGK): encoder and decoder grow the same tree as symbols go
+// by, so no frequencies need counting up front and no code table is stored. A symbol seen for
+// the first time is sent as the code of the NYT (not yet transmitted) leaf and its 8 bits.
+
+use super::bits::{BitReader, BitWriter};
+use crate::error::{Error, Result};
+
+const NYT: u16 = 256;
+const NONE: usize = usize::MAX;
+
+// Nodes are kept in order of non-increasing weight with the root first and siblings next to each
+// other, so the children of an internal node are always at child and child + 1
+#[derive(Clone, Copy)]
+struct Node {
+    weight: u64,
+    parent: usize,
+    child: usize,
+    symbol: u16,
+}
+
+pub struct AdaptiveHuffman {
+    nodes: Vec<Node>,
+    leaves: [usize; 257],
+}
+
+impl AdaptiveHuffman {
+    pub fn new() -> Self {
+        let mut leaves = [NONE; 257];
+        leaves[NYT as usize] = 0;
+        AdaptiveHuffman {
+            nodes: vec![Node { weight: 0, parent: NONE, child: NONE, symbol: NYT }],
+            leaves,
+        }
+    }
+
+    pub fn encode(&mut self, byte: u8, writer: &mut BitWriter) {
+        let known = self.leaves[byte as usize] != NONE;
+        let leaf = if known { self.leaves[byte as usize] } else { self.leaves[NYT as usize] };
+
+        // The path is found leaf to root, so collect it first and write it backwards
+        let mut path = Vec::new();
+        let mut node = leaf;
+        while node != 0 {
+            let parent = self.nodes[node].parent;
+            path.push(node != self.nodes[parent].child);
+            node = parent;
+        }
+        path.iter().rev().for_each(|&bit| writer.write_bit(bit));
+
+        if !known {
+            writer.write(8, byte as u32);
+        }
+        self.update(byte);
+    }
+
+    pub fn decode(&mut self, reader: &mut BitReader) -> Result<u8> {
+        let mut node = 0;
+        while self.nodes[node].child != NONE {
+            let bit = reader.read_bit().ok_or(Error::InvalidPayload("truncated adaptive Huffman data"))?;
+            node = self.nodes[node].child + bit as usize;
+        }
+
+        let byte = match self.nodes[node].symbol {
+            NYT => reader.read(8).ok_or(Error::InvalidPayload("truncated adaptive Huffman data"))? as u8,
+            symbol => symbol as u8,
+        };
+        self.update(byte);
+        Ok(byte)
+    }
+
+    // Count one more byte: a new byte splits the NYT leaf first, then every node from its leaf up
+    // is swapped with the first node of its weight before its weight goes up
+    fn update(&mut self, byte: u8) {
+        let mut node = self.leaves[byte as usize];
+        if node == NONE {
+            let nyt = self.leaves[NYT as usize];
+            let child = self.nodes.len();
+            self.nodes[nyt].child = child;
+            self.nodes[nyt].symbol = 0;
+            self.nodes.push(Node { weight: 0, parent: nyt, child: NONE, symbol: byte as u16 });
+            self.nodes.push(Node { weight: 0, parent: nyt, child: NONE, symbol: NYT });
+            self.leaves[byte as usize] = child;
+            self.leaves[NYT as usize] = child + 1;
+            node = child;
+        }
+
+        loop {
+            let weight = self.nodes[node].weight;
+            let mut leader = node;
+            while leader > 0 && self.nodes[leader - 1].weight == weight {
+                leader -= 1;
+            }
+            if leader != node && leader != self.nodes[node].parent {
+                self.swap(node, leader);
+                node = leader;
+            }
+
+            self.nodes[node].weight += 1;
+            if node == 0 {
+                break;
+            }
+            node = self.nodes[node].parent;
+        }
+    }
+
+    // Exchange the subtrees at two positions of equal weight, the positions keep their parents
+    fn swap(&mut self, a: usize, b: usize) {
+        let (node_a, node_b) = (self.nodes[a], self.nodes[b]);
+        self.nodes[a] = Node { parent: node_a.parent, ..node_b };
+        self.nodes[b] = Node { parent: node_b.parent, ..node_a };
+        self.relink(a);
+        self.relink(b);
+    }
+
+    fn relink(&mut self, position: usize) {
+        let node = self.nodes[position];
+        if node.child == NONE {
+            self.leaves[node.symbol as usize] = position;
+        } else {
+            self.nodes[node.child].parent = position;
+            self.nodes[node.child + 1].parent = position;
+        }
+    }
+}
+
+impl Default for AdaptiveHuffman {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn adaptive_huffman_encode(data: &[u8]) -> Vec<u8> {
+    let mut coder = AdaptiveHuffman::new();
+    let mut writer = BitWriter::new();
+    data.iter().for_each(|&byte| coder.encode(byte, &mut writer));
+    writer.finish()
+}
+
+// count is needed because the padding of the last byte could decode to more symbols
+pub fn adaptive_huffman_decode(data: &[u8], count: usize) -> Result<Vec<u8>> {
+    if count > data.len() * 8 {
+        return Err(Error::InvalidPayload("truncated adaptive Huffman data"));
+    }
+    let mut coder = AdaptiveHuffman::new();
+    let mut reader = BitReader::new(data);
+    (0..count).map(|_| coder.decode(&mut reader)).collect()
+}
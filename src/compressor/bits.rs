@@ -0,0 +1,84 @@
+// MSB-first bit packing, the order bzip2 and the canonical Huffman codes use
+
+pub struct BitWriter {
+    output: Vec<u8>,
+    buffer: u64,
+    n_bits: u32,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        BitWriter { output: Vec::new(), buffer: 0, n_bits: 0 }
+    }
+
+    // Write the low n_bits of value, n_bits is at most 32
+    pub fn write(&mut self, n_bits: u32, value: u32) {
+        debug_assert!(n_bits <= 32);
+        if n_bits == 0 {
+            return;
+        }
+        self.buffer = (self.buffer << n_bits) | (value as u64 & ((1u64 << n_bits) - 1));
+        self.n_bits += n_bits;
+
+        while self.n_bits >= 8 {
+            self.n_bits -= 8;
+            self.output.push((self.buffer >> self.n_bits) as u8);
+        }
+    }
+
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write(1, bit as u32);
+    }
+
+    // Pad the last byte with zero bits
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.n_bits > 0 {
+            self.output.push((self.buffer << (8 - self.n_bits)) as u8);
+        }
+        self.output
+    }
+}
+
+impl Default for BitWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader { data, pos: 0 }
+    }
+
+    // Read n_bits (at most 32), None once the data runs out
+    pub fn read(&mut self, n_bits: u32) -> Option<u32> {
+        debug_assert!(n_bits <= 32);
+        let mut value = 0u32;
+        for _ in 0..n_bits {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Some(value)
+    }
+
+    pub fn read_bit(&mut self) -> Option<bool> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit == 1)
+    }
+
+    // Skip to the start of the next byte
+    pub fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    // Bytes from the current (aligned) position to the end
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[(self.pos.div_ceil(8)).min(self.data.len())..]
+    }
+}
@@ -1,55 +1,304 @@
+// Sort rotation start positions instead of the rotations themselves, so memory stays linear
+pub(crate) fn sort_rotations<T: Ord>(text: &[T]) -> Vec<usize> {
+    let n = text.len();
+    sort_cycles(text, |_| (0, n), n)
+}
+
+// Sort positions by the infinite string read from them, where cycle(i) gives the start and
+// length of the cycle i wraps around in. Prefix doubling: after each round the positions are
+// ordered by their first 2k symbols, which keeps long runs and repeats (a diropql program,
+// a bzip2 block) at O(n log n). Sorting stops once the first `limit` symbols are in order.
+fn sort_cycles<T: Ord>(text: &[T], cycle: impl Fn(usize) -> (usize, usize), limit: usize) -> Vec<usize> {
+    let n = text.len();
+    let mut sorted_rotations: Vec<usize> = (0..n).collect();
+    sorted_rotations.sort_by(|&a, &b| text[a].cmp(&text[b]));
+    if n < 2 {
+        return sorted_rotations;
+    }
+
+    let forward = |i: usize, k: usize| {
+        let (start, len) = cycle(i);
+        start + (i - start + k % len) % len
+    };
+    let back = |i: usize, k: usize| {
+        let (start, len) = cycle(i);
+        start + (i - start + len - k % len) % len
+    };
+
+    let mut rank = vec![0usize; n];
+    for i in 1..n {
+        let (a, b) = (sorted_rotations[i - 1], sorted_rotations[i]);
+        rank[b] = rank[a] + (text[a] != text[b]) as usize;
+    }
+
+    let mut next = vec![0usize; n];
+    let mut start = vec![0usize; n];
+    let mut k = 1;
+    while k < limit && rank[sorted_rotations[n - 1]] < n - 1 {
+        // Positions ordered by their second half, then stably by their first half
+        for (j, &rotation) in sorted_rotations.iter().enumerate() {
+            next[j] = back(rotation, k);
+        }
+        start.fill(0);
+        for &rotation in &next {
+            start[rank[rotation]] += 1;
+        }
+        let mut sum = 0;
+        for count in start.iter_mut() {
+            let c = *count;
+            *count = sum;
+            sum += c;
+        }
+        for &rotation in &next {
+            sorted_rotations[start[rank[rotation]]] = rotation;
+            start[rank[rotation]] += 1;
+        }
+
+        next[sorted_rotations[0]] = 0;
+        for j in 1..n {
+            let (a, b) = (sorted_rotations[j - 1], sorted_rotations[j]);
+            let differs = (rank[a], rank[forward(a, k)]) != (rank[b], rank[forward(b, k)]);
+            next[b] = next[a] + differs as usize;
+        }
+        std::mem::swap(&mut rank, &mut next);
+        k *= 2;
+    }
+    sorted_rotations
+}
+
 pub fn bwt_encode(text: &str) -> (String, usize) {
     let message = format!("{}\0", text);
+    let chars: Vec<char> = message.chars().collect();
+    let n = chars.len();
 
-    let rotations: Vec<String> = (0..message.len())
-        .map(|i| format!("{}{}", &message[i..], &message[..i]))
-        .collect();
-
-    let mut sorted_rotations = rotations.clone();
-    sorted_rotations.sort();
+    // Bytes are cheaper to compare than chars, and most messages are ASCII
+    let sorted_rotations = if message.is_ascii() {
+        sort_rotations(message.as_bytes())
+    } else {
+        sort_rotations(&chars)
+    };
 
     let transformed_text: String = sorted_rotations
     .iter()
-    .map(|rotation| rotation.chars().last().unwrap())
+    .map(|&rotation| chars[(rotation + n - 1) % n])
     .collect();
     let index = sorted_rotations
-    .iter().position(|rotation| *rotation == message)
+    .iter().position(|&rotation| rotation == 0)
     .unwrap();
 
     (transformed_text, index)
 }
 
-pub fn bwt_decode(text: &String, index: usize) -> String {
-    let chars: Vec<char> = text.chars().collect();
-    let mut t = Vec::with_capacity(chars.len());
+// Same transform over raw bytes, with a 0 byte as the sentinel
+pub fn bwt_encode_bytes(data: &[u8]) -> (Vec<u8>, usize) {
+    let mut message = data.to_vec();
+    message.push(0);
+    let n = message.len();
+
+    let sorted_rotations = sort_rotations(&message);
 
-    for (i, &c) in chars.iter().enumerate() {
-        t.push((c, i));
+    let transformed_text: Vec<u8> = sorted_rotations
+    .iter()
+    .map(|&rotation| message[(rotation + n - 1) % n])
+    .collect();
+    let index = sorted_rotations
+    .iter().position(|&rotation| rotation == 0)
+    .unwrap();
+
+    (transformed_text, index)
+}
+
+// LF-mapping: the k-th occurrence of a symbol in the last column is its k-th occurrence in the
+// first column, and the first column is just the symbols in sorted order. next[row] is the
+// last-column row holding the symbol that starts row, so following it from the row of the
+// original text spells the text out in O(n).
+fn lf_next<T: Ord + Copy>(last_column: &[T]) -> Vec<usize> {
+    let mut starts = std::collections::BTreeMap::new();
+    for &symbol in last_column {
+        *starts.entry(symbol).or_insert(0usize) += 1;
+    }
+    let mut sum = 0;
+    for start in starts.values_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
+
+    let mut next = vec![0usize; last_column.len()];
+    for (row, symbol) in last_column.iter().enumerate() {
+        let start = starts.get_mut(symbol).unwrap();
+        next[*start] = row;
+        *start += 1;
     }
+    next
+}
 
-    t.sort();
+fn inverse_bwt<T: Ord + Copy>(last_column: &[T], index: usize) -> Vec<T> {
+    let next = lf_next(last_column);
+    let mut row = index;
+    let mut m = Vec::with_capacity(last_column.len());
 
-    let _l: Vec<usize> = t.iter().map(|&(_, idx)| idx).collect();
+    for _ in 0..last_column.len() {
+        row = next[row];
+        m.push(last_column[row]);
+    }
+    m
+}
 
-    let mut l_idx = index;
-    let mut m = String::new();
+// Same walk for bytes, with a counting sort instead of a map
+pub fn inverse_bwt_lf(last_column: &[u8], index: usize) -> Vec<u8> {
+    let mut starts = [0usize; 256];
+    for &byte in last_column {
+        starts[byte as usize] += 1;
+    }
+    let mut sum = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
 
-    for _ in chars.iter().take(chars.len()) {
-        let (cur_char, cur_idx) = t[l_idx];
-        m.push(cur_char);
-        l_idx = cur_idx;
+    let mut next = vec![0usize; last_column.len()];
+    for (row, &byte) in last_column.iter().enumerate() {
+        next[starts[byte as usize]] = row;
+        starts[byte as usize] += 1;
+    }
+
+    let mut row = index;
+    let mut m = Vec::with_capacity(last_column.len());
+    for _ in 0..last_column.len() {
+        row = next[row];
+        m.push(last_column[row]);
+    }
+    m
+}
+
+// Longest input the packed variant handles, the row has to fit in 24 bits
+pub const PACKED_MAX_LEN: usize = 1 << 24;
+
+// Cache-friendly variant in the style of bzip2: the next row and the byte at it share one u32,
+// so every step of the walk is a single random access instead of two.
+// last_column must be shorter than PACKED_MAX_LEN.
+pub fn inverse_bwt_packed(last_column: &[u8], index: usize) -> Vec<u8> {
+    assert!(last_column.len() < PACKED_MAX_LEN);
+    if last_column.is_empty() {
+        return Vec::new();
+    }
+
+    let mut starts = [0u32; 256];
+    for &byte in last_column {
+        starts[byte as usize] += 1;
+    }
+    let mut sum = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
+
+    let mut tt: Vec<u32> = last_column.iter().map(|&byte| byte as u32).collect();
+    for (row, &byte) in last_column.iter().enumerate() {
+        tt[starts[byte as usize] as usize] |= (row as u32) << 8;
+        starts[byte as usize] += 1;
+    }
+
+    let mut entry = tt[index];
+    let mut m = Vec::with_capacity(last_column.len());
+    for _ in 0..last_column.len() {
+        entry = tt[(entry >> 8) as usize];
+        m.push(entry as u8);
     }
     m
 }
 
-fn main() {
-    let message = "LET HIM COOK";
-    let (transformed_text, index) = bwt_encode(&message.to_string());
-    let i: usize = index as usize;
-    let decoded = bwt_decode(&transformed_text, i);
-    
-    println!("Message: {}", message);
-    println!("Transformed Text: {}", transformed_text);
-    println!("Index: {}", index);
-    println!("Decoded Text: {}", decoded);
+pub fn bwt_decode(text: &str, index: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    inverse_bwt(&chars, index).into_iter().collect()
+}
+
+// The decoded bytes still end with the sentinel
+pub fn bwt_decode_bytes(data: &[u8], index: usize) -> Vec<u8> {
+    if data.len() < PACKED_MAX_LEN {
+        inverse_bwt_packed(data, index)
+    } else {
+        inverse_bwt_lf(data, index)
+    }
+}
+
+// Duval's algorithm: split data into Lyndon words w1 >= w2 >= ... >= wk, returned as (start, len)
+fn lyndon_factors(data: &[u8]) -> Vec<(usize, usize)> {
+    let n = data.len();
+    let mut factors = Vec::new();
+    let mut i = 0;
+
+    while i < n {
+        let (mut j, mut k) = (i + 1, i);
+        while j < n && data[k] <= data[j] {
+            k = if data[k] < data[j] { i } else { k + 1 };
+            j += 1;
+        }
+        while i <= k {
+            factors.push((i, j - k));
+            i += j - k;
+        }
+    }
+    factors
+}
+
+// Bijective BWT (Gil and Scott): every rotation of every Lyndon factor, sorted by the infinite
+// repetition of the rotation, contributes the symbol before it. No sentinel and no primary
+// index are needed, and every byte string is the transform of exactly one other.
+pub fn bwts_encode(data: &[u8]) -> Vec<u8> {
+    let factors = lyndon_factors(data);
+    let mut cycle = vec![(0, 0); data.len()];
+    for &(start, len) in &factors {
+        cycle[start..start + len].fill((start, len));
+    }
+    let longest = factors.iter().map(|&(_, len)| len).max().unwrap_or(0);
+
+    // Two different infinite repetitions differ within the first len(u) + len(v) symbols
+    let sorted = sort_cycles(data, |i| cycle[i], 2 * longest);
+    sorted
+        .iter()
+        .map(|&i| {
+            let (start, len) = cycle[i];
+            data[start + (i - start + len - 1) % len]
+        })
+        .collect()
+}
+
+// The LF-mapping splits into one cycle per Lyndon factor. The smallest unvisited row always starts
+// the smallest remaining factor, which comes last, so the output is filled from the back.
+pub fn bwts_decode(data: &[u8]) -> Vec<u8> {
+    let mut starts = [0usize; 256];
+    for &byte in data {
+        starts[byte as usize] += 1;
+    }
+    let mut sum = 0;
+    for start in starts.iter_mut() {
+        let count = *start;
+        *start = sum;
+        sum += count;
+    }
+    let lf: Vec<usize> = data
+        .iter()
+        .map(|&byte| {
+            starts[byte as usize] += 1;
+            starts[byte as usize] - 1
+        })
+        .collect();
+
+    let mut visited = vec![false; data.len()];
+    let mut output = vec![0u8; data.len()];
+    let mut pos = data.len();
+    for first in 0..data.len() {
+        let mut row = first;
+        while !visited[row] {
+            visited[row] = true;
+            pos -= 1;
+            output[pos] = data[row];
+            row = lf[row];
+        }
+    }
+    output
 }
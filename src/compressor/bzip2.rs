@@ -0,0 +1,416 @@
+// Genuine .bz2 streams built from the same BWT -> MTF -> zero-run -> Huffman chain as diropqlz,
+// readable by the bzip2 tool and able to read what it writes
+
+use super::bits::{BitReader, BitWriter};
+use super::bwt::{bwt_decode_bytes, sort_rotations};
+use super::huffman::{canonical_codes, choose_tables, table_count, GROUP_SIZE};
+use super::rle::rle1_decode;
+use crate::error::{Error, Result};
+
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;     // BCD of pi
+const END_MAGIC: u64 = 0x1772_4538_5090;       // BCD of sqrt(pi)
+
+const MIN_GROUPS: usize = 2;
+const MAX_GROUPS: usize = 6;
+const MAX_SELECTORS: usize = 18002;
+const MAX_CODE_LEN: u8 = 20;                   // Longest code a decoder has to accept
+const MAX_ENCODE_LEN: u8 = 17;                 // Longest code bzip2 itself writes
+
+const RUNA: u16 = 0;
+const RUNB: u16 = 1;
+
+const TRUNCATED: Error = Error::InvalidBzip2("truncated stream");
+
+// CRC-32 with the 0x04c11db7 polynomial, fed MSB first
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn block_crc(data: &[u8]) -> u32 {
+    !data.iter().fold(0xffff_ffff, |crc: u32, &byte| (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize])
+}
+
+fn write_magic(writer: &mut BitWriter, magic: u64) {
+    writer.write(24, (magic >> 24) as u32);
+    writer.write(24, magic as u32 & 0xff_ffff);
+}
+
+// level is the block size in units of 100k, as in `bzip2 -1` .. `bzip2 -9`
+pub fn bzip2_encode(data: &[u8], level: u8) -> Vec<u8> {
+    let level = level.clamp(1, 9);
+    // bzip2 keeps 19 bytes of slack in every block, matching it keeps our blocks readable by old decoders
+    let max_block = 100_000 * level as usize - 19;
+
+    let mut writer = BitWriter::new();
+    for &byte in b"BZh" {
+        writer.write(8, byte as u32);
+    }
+    writer.write(8, (b'0' + level) as u32);
+
+    let mut combined_crc = 0u32;
+    let mut flush = |writer: &mut BitWriter, block: &[u8], original: &[u8]| {
+        let crc = block_crc(original);
+        combined_crc = combined_crc.rotate_left(1) ^ crc;
+        write_block(writer, block, crc);
+    };
+
+    // Initial RLE: runs of 4 to 255 equal bytes become the 4 bytes and a count of the rest.
+    // A run is never split across blocks, so every block decodes on its own.
+    let mut block = Vec::with_capacity(max_block);
+    let (mut block_start, mut i) = (0, 0);
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..].iter().take(255).take_while(|&&b| b == byte).count();
+        let size = if run >= 4 { 5 } else { run };
+
+        if block.len() + size > max_block {
+            flush(&mut writer, &block, &data[block_start..i]);
+            block.clear();
+            block_start = i;
+        }
+        if run >= 4 {
+            block.extend([byte; 4]);
+            block.push((run - 4) as u8);
+        } else {
+            block.extend(std::iter::repeat_n(byte, run));
+        }
+        i += run;
+    }
+    if !block.is_empty() {
+        flush(&mut writer, &block, &data[block_start..]);
+    }
+
+    write_magic(&mut writer, END_MAGIC);
+    writer.write(32, combined_crc);
+    writer.finish()
+}
+
+fn write_block(writer: &mut BitWriter, block: &[u8], crc: u32) {
+    let n = block.len();
+    let sorted_rotations = sort_rotations(block);
+    let orig_ptr = sorted_rotations.iter().position(|&rotation| rotation == 0).unwrap();
+    let last_column: Vec<u8> = sorted_rotations.iter().map(|&rotation| block[(rotation + n - 1) % n]).collect();
+
+    write_magic(writer, BLOCK_MAGIC);
+    writer.write(32, crc);
+    writer.write_bit(false);                        // Not randomised
+    writer.write(24, orig_ptr as u32);
+
+    // Bytes in use: one bit per range of 16, then one bit per byte of every range in use
+    let mut in_use = [false; 256];
+    for &byte in block {
+        in_use[byte as usize] = true;
+    }
+    let ranges: Vec<bool> = in_use.chunks(16).map(|range| range.contains(&true)).collect();
+    for &used in &ranges {
+        writer.write_bit(used);
+    }
+    for (range, &used) in in_use.chunks(16).zip(&ranges) {
+        if used {
+            range.iter().for_each(|&byte_used| writer.write_bit(byte_used));
+        }
+    }
+
+    let mut unseq = [0u8; 256];
+    let mut n_in_use = 0;
+    for byte in 0..256 {
+        if in_use[byte] {
+            unseq[byte] = n_in_use as u8;
+            n_in_use += 1;
+        }
+    }
+    let symbols = mtf_zero_runs(&last_column, &unseq, n_in_use);
+    let alpha_size = n_in_use + 2;
+
+    let (lengths, selectors) = choose_tables(&symbols, alpha_size, table_count(symbols.len()), MAX_ENCODE_LEN);
+    debug_assert!(selectors.len() <= MAX_SELECTORS);
+
+    writer.write(3, lengths.len() as u32);
+    writer.write(15, selectors.len() as u32);
+    let mut order: Vec<u8> = (0..lengths.len() as u8).collect();
+    for &selector in &selectors {
+        let j = order.iter().position(|&table| table == selector).unwrap();
+        (0..j).for_each(|_| writer.write_bit(true));
+        writer.write_bit(false);
+        order.remove(j);
+        order.insert(0, selector);
+    }
+
+    // Code lengths as deltas: 10 adds one, 11 takes one away, 0 moves to the next symbol
+    for table in &lengths {
+        let mut current = table[0];
+        writer.write(5, current as u32);
+        for &length in table {
+            while current < length {
+                writer.write(2, 0b10);
+                current += 1;
+            }
+            while current > length {
+                writer.write(2, 0b11);
+                current -= 1;
+            }
+            writer.write_bit(false);
+        }
+    }
+
+    let codes: Vec<Vec<u32>> = lengths.iter().map(|table| canonical_codes(table)).collect();
+    for (group, &selector) in symbols.chunks(GROUP_SIZE).zip(&selectors) {
+        let (table, table_codes) = (&lengths[selector as usize], &codes[selector as usize]);
+        for &symbol in group {
+            writer.write(table[symbol as usize] as u32, table_codes[symbol as usize]);
+        }
+    }
+}
+
+// MTF over the bytes in use, then runs of zeros written in bijective base 2 with RUNA (1) and RUNB (2)
+// as digits. Other positions move up by one to make room, and the block ends with EOB.
+fn mtf_zero_runs(last_column: &[u8], unseq: &[u8; 256], n_in_use: usize) -> Vec<u16> {
+    let mut order: Vec<u8> = (0..n_in_use).map(|value| value as u8).collect();
+    let mut symbols = Vec::with_capacity(last_column.len() + 1);
+    let mut zero_run = 0usize;
+
+    let flush_run = |symbols: &mut Vec<u16>, zero_run: &mut usize| {
+        if *zero_run == 0 {
+            return;
+        }
+        let mut run = *zero_run - 1;
+        loop {
+            symbols.push(if run & 1 == 1 { RUNB } else { RUNA });
+            if run < 2 {
+                break;
+            }
+            run = (run - 2) / 2;
+        }
+        *zero_run = 0;
+    };
+
+    for &byte in last_column {
+        let value = unseq[byte as usize];
+        let j = order.iter().position(|&v| v == value).unwrap();
+        if j == 0 {
+            zero_run += 1;
+            continue;
+        }
+        flush_run(&mut symbols, &mut zero_run);
+        order.remove(j);
+        order.insert(0, value);
+        symbols.push(j as u16 + 1);
+    }
+    flush_run(&mut symbols, &mut zero_run);
+    symbols.push(n_in_use as u16 + 1);
+    symbols
+}
+
+// Canonical decoder: the number of codes of each length and the symbols in code order
+struct DecodeTable {
+    counts: [u32; MAX_CODE_LEN as usize + 1],
+    symbols: Vec<u16>,
+}
+
+impl DecodeTable {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u32; MAX_CODE_LEN as usize + 1];
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..=MAX_CODE_LEN {
+            for (symbol, &l) in lengths.iter().enumerate() {
+                if l == length {
+                    counts[length as usize] += 1;
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        DecodeTable { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0u32);
+        for &count in &self.counts[1..] {
+            code |= reader.read_bit().ok_or(TRUNCATED)? as u32;
+            if code.wrapping_sub(first) < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(Error::InvalidBzip2("invalid Huffman code"))
+    }
+}
+
+// Decodes every stream in data, concatenated streams as written by `cat a.bz2 b.bz2` included
+pub fn bzip2_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut rest = data;
+
+    loop {
+        let mut reader = BitReader::new(rest);
+        for &expected in b"BZh" {
+            if reader.read(8) != Some(expected as u32) {
+                return Err(Error::InvalidBzip2("missing BZh signature"));
+            }
+        }
+        let level = reader.read(8).ok_or(TRUNCATED)?;
+        if !(b'1' as u32..=b'9' as u32).contains(&level) {
+            return Err(Error::InvalidBzip2("invalid block size"));
+        }
+        let max_block = 100_000 * (level - b'0' as u32) as usize;
+
+        let mut combined_crc = 0u32;
+        loop {
+            let magic = ((reader.read(24).ok_or(TRUNCATED)? as u64) << 24) | reader.read(24).ok_or(TRUNCATED)? as u64;
+            let crc = reader.read(32).ok_or(TRUNCATED)?;
+
+            match magic {
+                BLOCK_MAGIC => {
+                    let block = rle1_decode(&read_block(&mut reader, max_block)?);
+                    if block_crc(&block) != crc {
+                        return Err(Error::InvalidBzip2("block CRC mismatch"));
+                    }
+                    combined_crc = combined_crc.rotate_left(1) ^ crc;
+                    output.extend(block);
+                }
+                END_MAGIC if crc == combined_crc => break,
+                END_MAGIC => return Err(Error::InvalidBzip2("stream CRC mismatch")),
+                _ => return Err(Error::InvalidBzip2("invalid block signature")),
+            }
+        }
+
+        reader.align();
+        rest = reader.remaining();
+        if rest.is_empty() {
+            return Ok(output);
+        }
+    }
+}
+
+// Returns the block as it was before the BWT, still in its initial RLE form
+fn read_block(reader: &mut BitReader, max_block: usize) -> Result<Vec<u8>> {
+    if reader.read_bit().ok_or(TRUNCATED)? {
+        return Err(Error::InvalidBzip2("randomised blocks are not supported"));
+    }
+    let orig_ptr = reader.read(24).ok_or(TRUNCATED)? as usize;
+
+    let ranges = reader.read(16).ok_or(TRUNCATED)?;
+    let mut seq_to_unseq = Vec::new();
+    for range in 0..16 {
+        if ranges & (0x8000 >> range) != 0 {
+            let used = reader.read(16).ok_or(TRUNCATED)?;
+            seq_to_unseq.extend((0..16).filter(|&i| used & (0x8000 >> i) != 0).map(|i| (range * 16 + i) as u8));
+        }
+    }
+    if seq_to_unseq.is_empty() {
+        return Err(Error::InvalidBzip2("block uses no bytes"));
+    }
+    let alpha_size = seq_to_unseq.len() + 2;
+    let eob = alpha_size as u16 - 1;
+
+    let n_groups = reader.read(3).ok_or(TRUNCATED)? as usize;
+    if !(MIN_GROUPS..=MAX_GROUPS).contains(&n_groups) {
+        return Err(Error::InvalidBzip2("invalid number of Huffman tables"));
+    }
+    let n_selectors = reader.read(15).ok_or(TRUNCATED)? as usize;
+    if n_selectors == 0 {
+        return Err(Error::InvalidBzip2("no selectors"));
+    }
+    let mut order: Vec<u8> = (0..n_groups as u8).collect();
+    let mut selectors = Vec::with_capacity(n_selectors);
+    for _ in 0..n_selectors {
+        let mut j = 0;
+        while reader.read_bit().ok_or(TRUNCATED)? {
+            j += 1;
+            if j >= n_groups {
+                return Err(Error::InvalidBzip2("invalid selector"));
+            }
+        }
+        let table = order.remove(j);
+        order.insert(0, table);
+        selectors.push(table);
+    }
+
+    let mut tables = Vec::with_capacity(n_groups);
+    for _ in 0..n_groups {
+        let mut lengths = vec![0u8; alpha_size];
+        let mut current = reader.read(5).ok_or(TRUNCATED)? as u8;
+        for length in lengths.iter_mut() {
+            loop {
+                if !(1..=MAX_CODE_LEN).contains(&current) {
+                    return Err(Error::InvalidBzip2("invalid code length"));
+                }
+                if !reader.read_bit().ok_or(TRUNCATED)? {
+                    break;
+                }
+                if reader.read_bit().ok_or(TRUNCATED)? {
+                    current -= 1;
+                } else {
+                    current += 1;
+                }
+            }
+            *length = current;
+        }
+        tables.push(DecodeTable::new(&lengths));
+    }
+
+    // Huffman -> zero runs -> MTF, giving back the last column of the sorted rotations
+    let mut last_column = Vec::new();
+    let mut order = seq_to_unseq;
+    let (mut zero_run, mut run_weight) = (0usize, 1usize);
+    let mut selectors = selectors.iter();
+    let mut table = &tables[0];
+    let mut left_in_group = 0;
+
+    loop {
+        if left_in_group == 0 {
+            let &selector = selectors.next().ok_or(Error::InvalidBzip2("ran out of selectors"))?;
+            table = &tables[selector as usize];
+            left_in_group = GROUP_SIZE;
+        }
+        left_in_group -= 1;
+
+        let symbol = table.decode(reader)?;
+        if symbol == RUNA || symbol == RUNB {
+            zero_run += run_weight << symbol;
+            run_weight <<= 1;
+            if zero_run > max_block {
+                return Err(Error::InvalidBzip2("block too long"));
+            }
+            continue;
+        }
+
+        if zero_run > 0 {
+            last_column.extend(std::iter::repeat_n(order[0], zero_run));
+            zero_run = 0;
+            run_weight = 1;
+        }
+        if symbol == eob {
+            break;
+        }
+
+        let byte = order.remove(symbol as usize - 1);
+        order.insert(0, byte);
+        last_column.push(byte);
+        if last_column.len() > max_block {
+            return Err(Error::InvalidBzip2("block too long"));
+        }
+    }
+    if last_column.len() > max_block {
+        return Err(Error::InvalidBzip2("block too long"));
+    }
+    if orig_ptr >= last_column.len() {
+        return Err(Error::InvalidBzip2("origin pointer out of range"));
+    }
+
+    // The rotations were sorted without a sentinel, orig_ptr is the row of the block itself
+    Ok(bwt_decode_bytes(&last_column, orig_ptr))
+}
@@ -0,0 +1,148 @@
+// FM-index over the output of bwt_encode_bytes: counts and locates substrings of the original
+// block straight from its BWT, without inverting it
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+// Rows between two occurrence checkpoints
+const CHECKPOINT_RATE: usize = 64;
+pub const DEFAULT_SAMPLE_RATE: usize = 32;
+
+// Where a pattern occurs: the byte offset in the block and the number of separator bytes
+// before it, which turns a diropql program offset into a message offset ('o' ends every
+// byte) or a text offset into a line number ('\n')
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FmHit {
+    pub offset: usize,
+    pub separators: usize,
+}
+
+pub struct FmIndex {
+    bwt: Vec<u8>,
+    // First row of every symbol in the sorted first column
+    starts: [usize; 257],
+    // Occurrences of every symbol in bwt[..i * CHECKPOINT_RATE]
+    checkpoints: Vec<[u32; 256]>,
+    // Sampled rows and the offset and separator count of the rotation starting there
+    samples: HashMap<usize, FmHit>,
+    separator: u8,
+}
+
+impl FmIndex {
+    // bwt and index as returned by bwt_encode_bytes, the last byte of the block is the sentinel
+    pub fn new(bwt: &[u8], index: usize, separator: u8) -> Self {
+        Self::with_sample_rate(bwt, index, separator, DEFAULT_SAMPLE_RATE)
+    }
+
+    // One offset in sample_rate is kept; fewer samples make the index smaller and locate slower
+    pub fn with_sample_rate(bwt: &[u8], index: usize, separator: u8, sample_rate: usize) -> Self {
+        let sample_rate = sample_rate.max(1);
+        let n = bwt.len();
+
+        let mut counts = [0u32; 256];
+        let mut checkpoints = Vec::with_capacity(n / CHECKPOINT_RATE + 1);
+        for (row, &byte) in bwt.iter().enumerate() {
+            if row % CHECKPOINT_RATE == 0 {
+                checkpoints.push(counts);
+            }
+            counts[byte as usize] += 1;
+        }
+        checkpoints.push(counts);
+
+        let mut starts = [0usize; 257];
+        for byte in 0..256 {
+            starts[byte + 1] = starts[byte] + counts[byte] as usize;
+        }
+
+        let mut fm_index = FmIndex {
+            bwt: bwt.to_vec(),
+            starts,
+            checkpoints,
+            samples: HashMap::new(),
+            separator,
+        };
+
+        // Walk the block backwards from its first rotation, counting the separators behind us
+        let total = counts[separator as usize] as usize;
+        let (mut row, mut after) = (index, 0);
+        for step in 0..n {
+            let offset = (n - step) % n;
+            if offset.is_multiple_of(sample_rate) {
+                let separators = if offset == 0 { 0 } else { total - after };
+                fm_index.samples.insert(row, FmHit { offset, separators });
+            }
+            after += (bwt[row] == separator) as usize;
+            row = fm_index.lf(row);
+        }
+        fm_index
+    }
+
+    pub fn len(&self) -> usize {
+        self.bwt.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bwt.is_empty()
+    }
+
+    // How often byte occurs in the last column above row
+    pub fn occ(&self, byte: u8, row: usize) -> usize {
+        let checkpoint = row / CHECKPOINT_RATE;
+        let counted = self.bwt[checkpoint * CHECKPOINT_RATE..row].iter().filter(|&&b| b == byte).count();
+        self.checkpoints[checkpoint][byte as usize] as usize + counted
+    }
+
+    // Row of the rotation that starts one byte earlier
+    pub fn lf(&self, row: usize) -> usize {
+        let byte = self.bwt[row];
+        self.starts[byte as usize] + self.occ(byte, row)
+    }
+
+    // Rows of the sorted rotations that start with pattern, by backward search
+    pub fn range(&self, pattern: &[u8]) -> Range<usize> {
+        let mut range = 0..self.bwt.len();
+
+        for &byte in pattern.iter().rev() {
+            let start = self.starts[byte as usize];
+            range = start + self.occ(byte, range.start)..start + self.occ(byte, range.end);
+            if range.is_empty() {
+                return 0..0;
+            }
+        }
+        range
+    }
+
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        self.range(pattern).len()
+    }
+
+    // Every occurrence, sorted by offset. Rotations wrap around the sentinel, so patterns
+    // containing the sentinel byte can report matches that do not exist in the block.
+    pub fn locate(&self, pattern: &[u8]) -> Vec<FmHit> {
+        let mut hits: Vec<FmHit> = self.range(pattern).map(|row| self.locate_row(row)).collect();
+        hits.sort_by_key(|hit| hit.offset);
+        hits
+    }
+
+    // Step back to the nearest sampled row, then add the distance walked
+    fn locate_row(&self, mut row: usize) -> FmHit {
+        let (mut steps, mut separators) = (0, 0);
+        loop {
+            if let Some(sample) = self.samples.get(&row) {
+                return FmHit {
+                    offset: sample.offset + steps,
+                    separators: sample.separators + separators,
+                };
+            }
+            separators += (self.bwt[row] == self.separator) as usize;
+            row = self.lf(row);
+            steps += 1;
+        }
+    }
+
+    // Number of separator bytes in the whole block
+    pub fn separators(&self) -> usize {
+        let byte = self.separator as usize;
+        self.starts[byte + 1] - self.starts[byte]
+    }
+}
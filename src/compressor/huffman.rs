@@ -1,14 +1,17 @@
 use std::collections::{BinaryHeap, HashMap};
 use std::cmp::Ordering;
 
+use super::bits::BitWriter;
+use crate::error::{Error, Result};
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum HNodeType {
+pub enum HNodeType {
     Parent,
     Leaf,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
-struct HNode {
+pub struct HNode {
     node_val: u8,
     node_type: HNodeType,
     freq: i32,
@@ -33,11 +36,11 @@ impl Ord for HNode {
 
 impl PartialOrd for HNode {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        return Some(self.cmp(other));
+        Some(self.cmp(other))
     }
 }
 
-fn build_huffman_tree(text: &[u8]) -> HNode {
+pub fn build_huffman_tree(text: &[u8]) -> HNode {
     let mut char_map = vec![0; 256];
 
     for &c in text {
@@ -86,18 +89,18 @@ fn build_huffman_tree(text: &[u8]) -> HNode {
     pq.pop().unwrap()
 }
 
-fn build_codebook(htree_node: &HNode, codeword_stack: &mut String, codeword_map: &mut HashMap<u8, String>) {
+pub fn build_codebook(htree_node: &HNode, codeword_stack: &mut String, codeword_map: &mut HashMap<u8, String>) {
     if htree_node.node_type == HNodeType::Leaf {
         codeword_map.insert(htree_node.node_val, codeword_stack.clone());
     } else {
         if let Some(ref left_child) = htree_node.l_child {
             codeword_stack.push('1');
-            build_codebook(&*left_child, codeword_stack, codeword_map);
+            build_codebook(left_child, codeword_stack, codeword_map);
             codeword_stack.pop();
         }
         if let Some(ref right_child) = htree_node.r_child {
             codeword_stack.push('0');
-            build_codebook(&*right_child, codeword_stack, codeword_map);
+            build_codebook(right_child, codeword_stack, codeword_map);
             codeword_stack.pop();
         }
     }
@@ -155,17 +158,16 @@ pub fn build_canonical_codebook(codebook: &HashMap<u8, String>) -> Vec<(u8, Stri
         *value = zero_padding;
     }
 
-    let mut l_canon = match sorted_map.get(0) {
+    let mut l_canon = match sorted_map.first() {
         Some((_, codeword)) => codeword.len(),
         None => 0,
     };
     let mut c_canon = 0;
 
     let mut canonical_codebook = Vec::new();
-    canonical_codebook.push((sorted_map[0].0.into(), sorted_map[0].1.clone()));
+    canonical_codebook.push((sorted_map[0].0, sorted_map[0].1.clone()));
 
-    let mut iter = sorted_map.iter().skip(1);
-    while let Some((val, codeword)) = iter.next() {
+    for (val, codeword) in sorted_map.iter().skip(1) {
         c_canon += 1;
         let cur_len = codeword.len();
 
@@ -176,7 +178,7 @@ pub fn build_canonical_codebook(codebook: &HashMap<u8, String>) -> Vec<(u8, Stri
             c_canon &= (1 << l_canon) - 1;
         }
         let new_codeword = format!("{:0width$b}", c_canon, width = cur_len);
-        canonical_codebook.push((val.clone(), new_codeword));
+        canonical_codebook.push((*val, new_codeword));
     }
 
     canonical_codebook
@@ -201,7 +203,8 @@ pub fn canonical_encode_bits(message: &[u8], canonical_codebook: &[(u8, String)]
 }
 
 pub fn canon_length(canonical_codebook: &[(u8, String)]) -> Vec<usize> {
-    let mut lengths = vec![0; 10];
+    let symbols = canonical_codebook.iter().map(|&(value, _)| value as usize + 1).max().unwrap_or(0);
+    let mut lengths = vec![0; symbols];
 
     for (value, codeword) in canonical_codebook {
         lengths[*value as usize] = codeword.len();
@@ -227,32 +230,445 @@ pub fn canonical_decode_bits(data: &[u8], canonical_codebook: &[(u8, String)]) -
     decoded_text
 }
 
-fn main() {
-    let message: Vec<u8> = vec![5, 5, 6, 3, 3, 3, 1, 6, 6];
-    println!("Original Message: {:?}", message);
+// Canonical codebook in which codes of equal length are handed out in symbol order,
+// so the code lengths alone are enough to rebuild it
+pub fn canonical_codebook_from_lengths(lengths: &[(u8, usize)]) -> Vec<(u8, String)> {
+    let mut sorted_lengths = lengths.to_vec();
+    sorted_lengths.sort_by_key(|&(value, length)| (length, value));
+
+    let mut canonical_codebook = Vec::with_capacity(sorted_lengths.len());
+    let mut c_canon: u64 = 0;
+    let mut l_canon = sorted_lengths.first().map_or(0, |&(_, length)| length);
+
+    for (i, &(value, cur_len)) in sorted_lengths.iter().enumerate() {
+        if i > 0 {
+            c_canon += 1;
+        }
+        if cur_len > l_canon {
+            c_canon <<= cur_len - l_canon;
+            l_canon = cur_len;
+        }
+        canonical_codebook.push((value, format!("{:0width$b}", c_canon, width = cur_len)));
+    }
+
+    canonical_codebook
+}
+
+// One row of a code table, as exported to DOT and JSON
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeEntry {
+    pub symbol: u8,
+    pub frequency: u64,
+    pub code: String,
+}
+
+// Leaves of the tree from left to right, with the codes build_codebook gives them
+pub fn tree_entries(tree: &HNode) -> Vec<CodeEntry> {
+    fn walk(node: &HNode, code: &mut String, entries: &mut Vec<CodeEntry>) {
+        if node.node_type == HNodeType::Leaf {
+            entries.push(CodeEntry { symbol: node.node_val, frequency: node.freq as u64, code: code.clone() });
+            return;
+        }
+        for (child, bit) in [(&node.l_child, '1'), (&node.r_child, '0')] {
+            if let Some(child) = child {
+                code.push(bit);
+                walk(child, code, entries);
+                code.pop();
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    walk(tree, &mut String::new(), &mut entries);
+    entries
+}
+
+// Rows for a codebook such as build_canonical_codebook returns, freqs being indexed by symbol
+pub fn codebook_entries(codebook: &[(u8, String)], freqs: &[u32]) -> Vec<CodeEntry> {
+    codebook
+        .iter()
+        .map(|(symbol, code)| CodeEntry {
+            symbol: *symbol,
+            frequency: freqs.get(*symbol as usize).copied().unwrap_or(0) as u64,
+            code: code.clone(),
+        })
+        .collect()
+}
+
+// Printable ASCII as itself, anything else (and the quotes DOT would need escaped) in hex
+pub fn symbol_label(symbol: u8) -> String {
+    match symbol {
+        b'"' | b'\\' => format!("0x{:02x}", symbol),
+        _ if symbol.is_ascii_graphic() => format!("'{}'", symbol as char),
+        _ => format!("0x{:02x}", symbol),
+    }
+}
+
+// Graphviz digraph of the tree the codes describe: every edge is labelled with its bit, inner
+// nodes with the total frequency below them and leaves with their symbol, frequency and code
+pub fn entries_to_dot(entries: &[CodeEntry]) -> String {
+    // Rebuild the tree as a trie of the codes, children indexed by bit
+    struct Node {
+        children: [Option<usize>; 2],
+        frequency: u64,
+        leaf: Option<usize>,
+    }
+    let mut nodes = vec![Node { children: [None; 2], frequency: 0, leaf: None }];
+    for (i, entry) in entries.iter().enumerate() {
+        let mut node = 0;
+        nodes[0].frequency += entry.frequency;
+        for bit in entry.code.bytes().map(|bit| (bit == b'1') as usize) {
+            node = match nodes[node].children[bit] {
+                Some(child) => child,
+                None => {
+                    nodes.push(Node { children: [None; 2], frequency: 0, leaf: None });
+                    nodes[node].children[bit] = Some(nodes.len() - 1);
+                    nodes.len() - 1
+                }
+            };
+            nodes[node].frequency += entry.frequency;
+        }
+        nodes[node].leaf = Some(i);
+    }
 
-    let huffman_tree = build_huffman_tree(&message);
+    let mut dot = String::from("digraph huffman {\n    node [shape=circle];\n");
+    if entries.is_empty() {
+        return dot + "}\n";
+    }
+    for (id, node) in nodes.iter().enumerate() {
+        match node.leaf {
+            Some(i) => {
+                let entry = &entries[i];
+                dot.push_str(&format!(
+                    "    n{} [shape=box, label=\"{}\\n{}\\n{}\"];\n",
+                    id,
+                    symbol_label(entry.symbol),
+                    entry.frequency,
+                    entry.code
+                ));
+            }
+            None => dot.push_str(&format!("    n{} [label=\"{}\"];\n", id, node.frequency)),
+        }
+        for (bit, child) in node.children.iter().enumerate() {
+            if let Some(child) = child {
+                dot.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", id, child, bit));
+            }
+        }
+    }
+    dot + "}\n"
+}
 
-    let mut codeword_map: HashMap<u8, String> = HashMap::new();
-    build_codebook(&huffman_tree, &mut String::new(), &mut codeword_map);
-    //println!("{:?}", huffman_tree);
-    println!("Original Codebook: {:?}", codeword_map);
+// JSON array of {"symbol", "frequency", "code", "length"} objects, symbols given as numbers
+pub fn entries_to_json(entries: &[CodeEntry]) -> String {
+    let rows: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "  {{\"symbol\": {}, \"frequency\": {}, \"code\": \"{}\", \"length\": {}}}",
+                entry.symbol,
+                entry.frequency,
+                entry.code,
+                entry.code.len()
+            )
+        })
+        .collect();
+    match rows.is_empty() {
+        true => "[]\n".to_string(),
+        false => format!("[\n{}\n]\n", rows.join(",\n")),
+    }
+}
 
-    let canonical_codebook = build_canonical_codebook(&codeword_map);
-    println!("Canonical Codebook: {:?}", canonical_codebook);
+pub fn pack_bits(bits: &[u8]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| chunk.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | (bit << (7 - i))))
+        .collect()
+}
 
-    let encoded_bits = encode_huffman(&message, &codeword_map);
-    println!("Encoded Bits: {:?}", encoded_bits);
+pub fn unpack_bits(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter()
+        .flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+        .collect()
+}
 
-    let canonical_encoded_bits = canonical_encode_bits(&message, &canonical_codebook);
-    println!("Canonical Encoded Bits: {:?}", canonical_encoded_bits);
+// Longest code the huffman stage writes, so every code length fits in four bits
+pub const MAX_CODE_LEN: u8 = 15;
+
+// Optimal code lengths no longer than max_len by package-merge, for any number of symbols.
+// Unused symbols get length 0 and a lone symbol length 1; None when the used symbols
+// cannot all get a code of max_len bits or less.
+pub fn limited_code_lengths(freqs: &[u32], max_len: u8) -> Option<Vec<u8>> {
+    let mut leaves: Vec<usize> = (0..freqs.len()).filter(|&symbol| freqs[symbol] > 0).collect();
+    leaves.sort_by_key(|&symbol| (freqs[symbol], symbol));
+
+    let mut lengths = vec![0u8; freqs.len()];
+    let n = leaves.len();
+    if n <= 1 {
+        leaves.iter().for_each(|&symbol| lengths[symbol] = 1);
+        return Some(lengths);
+    }
+    if max_len == 0 || (max_len < 64 && n > 1usize << max_len) {
+        return None;
+    }
 
-    let canon_length = canon_length(&canonical_codebook);
-    println!("Canon Length: {:?}", canon_length);
+    // One list per depth, deepest first: the leaves merged with pairs from the list below.
+    // Only whether an item is a package is kept, the leaves in a list are always in order.
+    let weights: Vec<u64> = leaves.iter().map(|&symbol| freqs[symbol] as u64).collect();
+    let mut levels: Vec<Vec<bool>> = vec![vec![false; n]];
+    let mut previous = weights.clone();
+    // No code is ever longer than n - 1 bits, deeper lists would change nothing
+    for _ in 1..(max_len as usize).min(n - 1) {
+        let packages: Vec<u64> = previous.chunks_exact(2).map(|pair| pair[0] + pair[1]).collect();
+        let (mut merged, mut is_package) = (Vec::with_capacity(n + packages.len()), Vec::with_capacity(n + packages.len()));
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < packages.len() {
+            if j == packages.len() || (i < n && weights[i] <= packages[j]) {
+                merged.push(weights[i]);
+                is_package.push(false);
+                i += 1;
+            } else {
+                merged.push(packages[j]);
+                is_package.push(true);
+                j += 1;
+            }
+        }
+        levels.push(is_package);
+        previous = merged;
+    }
 
-    let decoded_message = decode_huffman(&encoded_bits, &codeword_map);
-    println!("Decoded Message (Huffman only): {:?}", decoded_message); 
+    // The cheapest 2n - 2 items at the top, then the packages they use one depth further down;
+    // every time a leaf is picked its code gets a bit longer
+    let mut count = 2 * n - 2;
+    for is_package in levels.iter().rev() {
+        let packages = is_package[..count].iter().filter(|&&package| package).count();
+        leaves[..count - packages].iter().for_each(|&symbol| lengths[symbol] += 1);
+        count = 2 * packages;
+    }
+    Some(lengths)
+}
 
-    let c_decoded_message = canonical_decode_bits(&canonical_encoded_bits, &canonical_codebook);
-    println!("Decoded Message (Canonical): {:?}", c_decoded_message);
+// Codes handed out by increasing length, and by symbol within a length; length 0 means unused
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let mut codes = vec![0u32; lengths.len()];
+    let mut code = 0u32;
+    for length in 1..=lengths.iter().copied().max().unwrap_or(0) {
+        for (symbol, &l) in lengths.iter().enumerate() {
+            if l == length {
+                codes[symbol] = code;
+                code += 1;
+            }
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+// Symbols coded with the same table when there are several
+pub const GROUP_SIZE: usize = 50;
+pub const MAX_TABLES: usize = 6;
+const TABLE_ITERATIONS: usize = 4;
+
+// bzip2's number of tables for a block of n_symbols symbols
+pub fn table_count(n_symbols: usize) -> usize {
+    match n_symbols {
+        0..=199 => 2,
+        200..=599 => 3,
+        600..=1199 => 4,
+        1200..=2399 => 5,
+        _ => MAX_TABLES,
+    }
+}
+
+// Pick n_groups tables the way bzip2 does: split the alphabet into bands of equal frequency,
+// then let every group of GROUP_SIZE symbols choose its cheapest table and rebuild the tables
+// from those choices a few times. Every symbol below alpha_size gets a code in every table.
+pub fn choose_tables(symbols: &[u16], alpha_size: usize, n_groups: usize, max_len: u8) -> (Vec<Vec<u8>>, Vec<u8>) {
+
+    let mut freqs = vec![0u32; alpha_size];
+    for &symbol in symbols {
+        freqs[symbol as usize] += 1;
+    }
+
+    let mut lengths = vec![vec![0u8; alpha_size]; n_groups];
+    let (mut remaining, mut band_start) = (symbols.len() as u32, 0usize);
+    for n_part in (1..=n_groups).rev() {
+        let target = remaining / n_part as u32;
+        let (mut band_end, mut band_freq) = (band_start, 0);
+        while band_end < alpha_size && (band_freq < target || band_end == band_start) {
+            band_freq += freqs[band_end];
+            band_end += 1;
+        }
+        if band_end > band_start + 1 && n_part != n_groups && n_part != 1 && (n_groups - n_part) % 2 == 1 {
+            band_end -= 1;
+            band_freq -= freqs[band_end];
+        }
+        for (v, length) in lengths[n_part - 1].iter_mut().enumerate() {
+            *length = if (band_start..band_end).contains(&v) { 0 } else { 15 };
+        }
+        band_start = band_end;
+        remaining -= band_freq;
+    }
+
+    let mut selectors = Vec::new();
+    for _ in 0..TABLE_ITERATIONS {
+        let mut table_freqs = vec![vec![0u32; alpha_size]; n_groups];
+        selectors.clear();
+
+        for group in symbols.chunks(GROUP_SIZE) {
+            let cost = |table: &Vec<u8>| group.iter().map(|&symbol| table[symbol as usize] as u32).sum::<u32>();
+            let best = (0..n_groups).min_by_key(|&t| (cost(&lengths[t]), t)).unwrap();
+            selectors.push(best as u8);
+            for &symbol in group {
+                table_freqs[best][symbol as usize] += 1;
+            }
+        }
+        for (table, freqs) in lengths.iter_mut().zip(&table_freqs) {
+            // Every symbol of the alphabet needs a code, used or not
+            let weights: Vec<u32> = freqs.iter().map(|&freq| freq.max(1)).collect();
+            *table = limited_code_lengths(&weights, max_len).unwrap();
+        }
+    }
+    (lengths, selectors)
+}
+
+// Packs the canonical code of every byte of data, MSB first; lengths holds one entry per byte value
+pub fn huffman_encode(data: &[u8], lengths: &[u8]) -> Vec<u8> {
+    let codes = canonical_codes(lengths);
+    let mut writer = BitWriter::new();
+    for &byte in data {
+        writer.write(lengths[byte as usize] as u32, codes[byte as usize]);
+    }
+    writer.finish()
+}
+
+// Packs every group of GROUP_SIZE symbols with the table its selector names
+pub fn huffman_encode_groups(symbols: &[u16], tables: &[Vec<u8>], selectors: &[u8]) -> Vec<u8> {
+    let codes: Vec<Vec<u32>> = tables.iter().map(|lengths| canonical_codes(lengths)).collect();
+    let mut writer = BitWriter::new();
+    for (group, &selector) in symbols.chunks(GROUP_SIZE).zip(selectors) {
+        let (lengths, codes) = (&tables[selector as usize], &codes[selector as usize]);
+        for &symbol in group {
+            writer.write(lengths[symbol as usize] as u32, codes[symbol as usize]);
+        }
+    }
+    writer.finish()
+}
+
+// Bits looked up at once; codes up to this long take one table read
+const LOOKUP_BITS: u32 = 10;
+// Longest code the table decoder handles, it peeks 32 bits at a time
+pub const MAX_DECODE_LEN: u8 = 32;
+
+// Canonical Huffman decoder over packed bytes: a table indexed by the next LOOKUP_BITS bits
+// gives the symbol and length of every short code, longer codes are found from the first
+// code and symbol index of every length
+pub struct HuffmanDecoder {
+    lookup: Vec<(u16, u8)>,
+    counts: [u64; MAX_DECODE_LEN as usize + 1],
+    first_code: [u64; MAX_DECODE_LEN as usize + 1],
+    first_index: [usize; MAX_DECODE_LEN as usize + 1],
+    symbols: Vec<u16>,
+    max_len: u8,
+}
+
+impl HuffmanDecoder {
+    // lengths holds one entry per symbol, 0 for unused ones; codes may be incomplete but not
+    // oversubscribed
+    pub fn new(lengths: &[u8]) -> Result<Self> {
+        let max_len = lengths.iter().copied().max().unwrap_or(0);
+        if max_len > MAX_DECODE_LEN || lengths.len() > 1 << 16 {
+            return Err(Error::InvalidHeader("invalid Huffman code length"));
+        }
+
+        let mut counts = [0u64; MAX_DECODE_LEN as usize + 1];
+        lengths.iter().for_each(|&length| counts[length as usize] += 1);
+        counts[0] = 0;
+
+        // Kraft's inequality: every length doubles the codes left, each code used takes one
+        let mut left = 1u64;
+        for &count in &counts[1..] {
+            left = (left << 1).checked_sub(count).ok_or(Error::InvalidHeader("oversubscribed Huffman code"))?;
+        }
+
+        let mut first_code = [0u64; MAX_DECODE_LEN as usize + 1];
+        let mut first_index = [0usize; MAX_DECODE_LEN as usize + 1];
+        let (mut code, mut index) = (0u64, 0usize);
+        for length in 1..=MAX_DECODE_LEN as usize {
+            first_code[length] = code;
+            first_index[length] = index;
+            code = (code + counts[length]) << 1;
+            index += counts[length] as usize;
+        }
+
+        let mut symbols: Vec<u16> = (0..lengths.len()).filter(|&symbol| lengths[symbol] > 0).map(|symbol| symbol as u16).collect();
+        symbols.sort_by_key(|&symbol| (lengths[symbol as usize], symbol));
+
+        let mut lookup = vec![(0u16, 0u8); 1 << LOOKUP_BITS];
+        let codes = canonical_codes(lengths);
+        for &symbol in &symbols {
+            let length = lengths[symbol as usize];
+            if length as u32 > LOOKUP_BITS {
+                break;
+            }
+            let spare = LOOKUP_BITS - length as u32;
+            let start = (codes[symbol as usize] << spare) as usize;
+            lookup[start..start + (1 << spare)].fill((symbol, length));
+        }
+
+        Ok(HuffmanDecoder { lookup, counts, first_code, first_index, symbols, max_len })
+    }
+
+    // The symbol whose code starts the 32-bit window, and the length of that code
+    fn decode_window(&self, window: u32) -> Option<(u16, u8)> {
+        let entry = self.lookup[(window >> (32 - LOOKUP_BITS)) as usize];
+        if entry.1 > 0 {
+            return Some(entry);
+        }
+        for length in LOOKUP_BITS as usize + 1..=self.max_len as usize {
+            let offset = ((window as u64) >> (32 - length)).wrapping_sub(self.first_code[length]);
+            if offset < self.counts[length] {
+                return Some((self.symbols[self.first_index[length] + offset as usize], length as u8));
+            }
+        }
+        None
+    }
+
+    // Decode count symbols from the start of data; the padding after them is ignored
+    pub fn decode(&self, data: &[u8], count: usize) -> Result<Vec<u16>> {
+        // Every code takes at least one bit
+        if count > data.len() * 8 {
+            return Err(Error::InvalidPayload("truncated Huffman data"));
+        }
+        let mut output = Vec::with_capacity(count);
+        self.decode_into(data, &mut 0, count, &mut output)?;
+        Ok(output)
+    }
+
+    // Decode count symbols starting at bit pos, which is moved past them
+    pub fn decode_into(&self, data: &[u8], pos: &mut usize, count: usize, output: &mut Vec<u16>) -> Result<()> {
+        let total_bits = data.len() * 8;
+        for _ in 0..count {
+            let (symbol, length) = self.decode_window(peek32(data, *pos)).ok_or(Error::InvalidPayload("invalid Huffman code"))?;
+            *pos += length as usize;
+            if *pos > total_bits {
+                return Err(Error::InvalidPayload("truncated Huffman data"));
+            }
+            output.push(symbol);
+        }
+        Ok(())
+    }
+}
+
+// The 32 bits starting at bit pos, zero past the end of data
+fn peek32(data: &[u8], pos: usize) -> u32 {
+    let byte = pos / 8;
+    let word = match data.get(byte..byte + 8) {
+        Some(bytes) => u64::from_be_bytes(bytes.try_into().unwrap()),
+        None => {
+            let mut bytes = [0u8; 8];
+            let tail = data.get(byte..).unwrap_or_default();
+            bytes[..tail.len().min(8)].copy_from_slice(&tail[..tail.len().min(8)]);
+            u64::from_be_bytes(bytes)
+        }
+    };
+    (word << (pos % 8) >> 32) as u32
 }
@@ -1,3 +1,10 @@
+pub mod adaptive_huffman;
+pub mod bits;
 pub mod bwt;
+pub mod bzip2;
+pub mod fm_index;
+pub mod huffman;
 pub mod mtf;
+pub mod range_coder;
+pub mod rans;
 pub mod rle;
@@ -1,46 +1,377 @@
-use std::io;
+use crate::error::{Error, Result};
+use crate::pipeline::{read_varint, write_varint};
 
-pub fn mtf_encode(text: &String, alphabet: &str) -> Vec<u8> {
+// Indices are single bytes, so an alphabet holds at most 256 symbols
+pub const MAX_ALPHABET: usize = 256;
+// The largest byte of inversion frequencies fills the slots left over without costing a byte, so
+// the data cannot bound how many it takes
+pub const MAX_FREE_FILL: usize = 1 << 24;
+
+pub fn mtf_encode(text: &str, alphabet: &str) -> Result<Vec<u8>> {
     let mut lst: Vec<char> = alphabet.chars().collect();    // Convert the alphabet string into a character vector
     let mut output_arr: Vec<u8> = Vec::new();               // Initialize an empty vector to store the encoded values
+    if lst.len() > MAX_ALPHABET {
+        return Err(Error::InvalidAlphabet("more than 256 symbols"));
+    }
 
     for input_char in text.chars() {
-        let curr_index = lst.iter().position(|&c| c == input_char).unwrap_or_default();    // Find the index of the input character in the alphabet
+        let curr_index = lst.iter().position(|&c| c == input_char).ok_or(Error::UnknownSymbol(input_char))?;    // Find the index of the input character in the alphabet
         let temp_char = lst[curr_index];                                        // Retrieve the character at the current index
         output_arr.push(curr_index as u8);                                      // Add the current index to the output array
         lst.remove(curr_index);                                                 // Remove the current character from the list
         lst.insert(0, temp_char);                                               // Insert the current character at the front of the list
     }
-    output_arr
+    Ok(output_arr)
 }
 
-pub fn mtf_decode(data: &Vec<u8>, alphabet: &str) -> String {
+pub fn mtf_decode(data: &[u8], alphabet: &str) -> Result<String> {
     let mut lst: Vec<char> = alphabet.chars().collect();    // Convert the alphabet string into a character vector
     let mut output_text = String::new();                    // Initialize an empty string to store the decoded text
 
     for &index in data {
-        let decoded_char = lst[index as usize];     // Retrieve the character at the given index from the list
+        let decoded_char = *lst.get(index as usize).ok_or(OUTSIDE_ALPHABET)?;    // Retrieve the character at the given index from the list
         output_text.push(decoded_char);             // Append the decoded character to the output text
         lst.remove(index as usize);                 // Remove the decoded character from the list
         lst.insert(0, decoded_char);                // Insert the decoded character at the front of the list
     }
-    output_text
+    Ok(output_text)
+}
+
+const OUTSIDE_ALPHABET: Error = Error::InvalidPayload("move-to-front index outside the alphabet");
+
+// Every byte value in order, the alphabet that accepts any input
+pub fn byte_alphabet() -> Vec<u8> {
+    (0..=255).collect()
+}
+
+// The bytes that occur in data, in order; storing it costs one byte per symbol
+pub fn input_alphabet(data: &[u8]) -> Vec<u8> {
+    let mut seen = [false; 256];
+    data.iter().for_each(|&b| seen[b as usize] = true);
+    (0..=255).filter(|&b| seen[b as usize]).collect()
+}
+
+// The list as a fixed array, moved with one in-place shift instead of a Vec::remove and insert,
+// and an inverse-position table so a symbol is found without scanning the list. Every symbol the
+// shift moves has its position bumped, which is cheap after the BWT, where most indices are 0.
+// Decoding only looks symbols up by index, so its tables skip the positions.
+pub struct MtfTable {
+    symbols: [u8; 256],
+    len: usize,
+    // Index of every byte in symbols, NOT_IN_ALPHABET for bytes outside the alphabet
+    positions: [u16; 256],
+    indexed: bool,
+}
+
+const NOT_IN_ALPHABET: u16 = u16::MAX;
+
+impl MtfTable {
+    pub fn new(alphabet: &[u8]) -> Result<Self> {
+        Self::with_index(alphabet, true)
+    }
+
+    // index_of scans the list instead of reading the positions
+    pub fn for_decoding(alphabet: &[u8]) -> Result<Self> {
+        Self::with_index(alphabet, false)
+    }
+
+    fn with_index(alphabet: &[u8], indexed: bool) -> Result<Self> {
+        if alphabet.len() > MAX_ALPHABET {
+            return Err(Error::InvalidAlphabet("more than 256 symbols"));
+        }
+        let mut table =
+            MtfTable { symbols: [0; 256], len: alphabet.len(), positions: [NOT_IN_ALPHABET; 256], indexed };
+        for (index, &byte) in alphabet.iter().enumerate() {
+            if table.positions[byte as usize] != NOT_IN_ALPHABET {
+                return Err(Error::InvalidAlphabet("repeated symbol"));
+            }
+            table.positions[byte as usize] = index as u16;
+        }
+        table.symbols[..alphabet.len()].copy_from_slice(alphabet);
+        Ok(table)
+    }
+
+    pub fn symbols(&self) -> &[u8] {
+        &self.symbols[..self.len]
+    }
+
+    pub fn index_of(&self, byte: u8) -> Option<usize> {
+        if !self.indexed {
+            return self.symbols().iter().position(|&b| b == byte);
+        }
+        match self.positions[byte as usize] {
+            NOT_IN_ALPHABET => None,
+            index => Some(index as usize),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<u8> {
+        self.symbols().get(index).copied()
+    }
+
+    // Move the symbol at index to target, shifting the ones in between back by one
+    pub fn move_to(&mut self, index: usize, target: usize) {
+        if index != target {
+            let byte = self.symbols[index];
+            self.symbols.copy_within(target..index, target + 1);
+            self.symbols[target] = byte;
+            if !self.indexed {
+                return;
+            }
+            for (position, &moved) in self.symbols[target..=index].iter().enumerate() {
+                self.positions[moved as usize] = (target + position) as u16;
+            }
+        }
+    }
+}
+
+// Byte version of mtf_encode, the symbols are the bytes of alphabet
+pub fn mtf_encode_bytes(data: &[u8], alphabet: &[u8]) -> Result<Vec<u8>> {
+    let mut table = MtfTable::new(alphabet)?;
+    let mut output = Vec::with_capacity(data.len());
+
+    for &byte in data {
+        let index = table.index_of(byte).ok_or(Error::UnknownSymbol(byte as char))?;
+        output.push(index as u8);
+        table.move_to(index, 0);
+    }
+    Ok(output)
+}
+
+pub fn mtf_decode_bytes(data: &[u8], alphabet: &[u8]) -> Result<Vec<u8>> {
+    let mut table = MtfTable::for_decoding(alphabet)?;
+    let mut output = Vec::with_capacity(data.len());
+
+    for &index in data {
+        let byte = table.get(index as usize).ok_or(OUTSIDE_ALPHABET)?;
+        output.push(byte);
+        table.move_to(index as usize, 0);
+    }
+    Ok(output)
+}
+
+// Symbol-ranking transforms that replace move-to-front after the BWT. The list-update
+// variants only differ in where a symbol goes once it has been coded; inversion frequencies
+// is not a list update at all and has its own functions below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MtfVariant {
+    // Every coded symbol moves to the front
+    MoveToFront,
+    // Balkenhol's MTF-1: from position 1 to the front, from further back to position 1
+    Mtf1,
+    // MTF-2: like MTF-1, but position 1 only moves to the front if the last index was not 0
+    Mtf2,
+    // Albers' Timestamp(0): in front of the first symbol requested at most once since this one was
+    Timestamp,
+    // Weighted frequency count: ordered by a count of recent occurrences that decays with age
+    WeightedFrequency,
+    // Arnavut's inversion frequencies, coded by inversion_frequencies_encode
+    InversionFrequencies,
+}
+
+impl MtfVariant {
+    pub const ALL: [MtfVariant; 6] = [
+        MtfVariant::MoveToFront,
+        MtfVariant::Mtf1,
+        MtfVariant::Mtf2,
+        MtfVariant::Timestamp,
+        MtfVariant::WeightedFrequency,
+        MtfVariant::InversionFrequencies,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MtfVariant::MoveToFront => "mtf",
+            MtfVariant::Mtf1 => "mtf1",
+            MtfVariant::Mtf2 => "mtf2",
+            MtfVariant::Timestamp => "ts0",
+            MtfVariant::WeightedFrequency => "wfc",
+            MtfVariant::InversionFrequencies => "if",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MtfVariant> {
+        MtfVariant::ALL.into_iter().find(|variant| variant.name() == name)
+    }
+}
+
+// WFC weights lose 1/16 per symbol coded and a use adds WFC_HIT
+const WFC_DECAY_SHIFT: u32 = 4;
+const WFC_HIT: u32 = 1 << 16;
+
+// The list both sides keep in step, with the history the variants look at
+struct RankList {
+    table: MtfTable,
+    variant: MtfVariant,
+    last_index: usize,
+    time: i64,
+    last_use: [i64; 256],
+    previous_use: [i64; 256],
+    weights: [u32; 256],
+}
+
+impl RankList {
+    fn new(table: MtfTable, variant: MtfVariant) -> Self {
+        RankList {
+            table,
+            variant,
+            last_index: 0,
+            time: 0,
+            last_use: [-1; 256],
+            previous_use: [-1; 256],
+            weights: [0; 256],
+        }
+    }
+
+    // Update the list after the symbol at index was coded
+    fn promote(&mut self, index: usize) {
+        let symbol = self.table.symbols[index];
+        let target = match self.variant {
+            MtfVariant::MoveToFront | MtfVariant::InversionFrequencies => 0,
+            MtfVariant::Mtf1 => match index {
+                0 | 1 => 0,
+                _ => 1,
+            },
+            MtfVariant::Mtf2 => match index {
+                0 => 0,
+                1 if self.last_index != 0 => 0,
+                _ => 1,
+            },
+            MtfVariant::Timestamp => {
+                let since = self.last_use[symbol as usize];
+                self.table.symbols[..index]
+                    .iter()
+                    .position(|&other| self.previous_use[other as usize] < since)
+                    .unwrap_or(index)
+            }
+            MtfVariant::WeightedFrequency => {
+                self.weights.iter_mut().for_each(|weight| *weight -= *weight >> WFC_DECAY_SHIFT);
+                self.weights[symbol as usize] = self.weights[symbol as usize].saturating_add(WFC_HIT);
+                let weight = self.weights[symbol as usize];
+                self.table.symbols[..index]
+                    .iter()
+                    .rposition(|&other| self.weights[other as usize] > weight)
+                    .map_or(0, |position| position + 1)
+            }
+        };
+
+        self.table.move_to(index, target);
+        self.last_index = index;
+        self.previous_use[symbol as usize] = self.last_use[symbol as usize];
+        self.last_use[symbol as usize] = self.time;
+        self.time += 1;
+    }
 }
 
-fn main() {
-    let mut input_text = String::new();
-    let mut alphabet = String::new();
+// List-update variants of mtf_encode_bytes
+pub fn rank_encode(data: &[u8], alphabet: &[u8], variant: MtfVariant) -> Result<Vec<u8>> {
+    let mut list = RankList::new(MtfTable::new(alphabet)?, variant);
+    let mut output = Vec::with_capacity(data.len());
 
-    println!("Enter the input text: ");
-    io::stdin().read_line(&mut input_text).expect("Failed to read input.");
-    let input_text = input_text.trim();
+    for &byte in data {
+        let index = list.table.index_of(byte).ok_or(Error::UnknownSymbol(byte as char))?;
+        output.push(index as u8);
+        list.promote(index);
+    }
+    Ok(output)
+}
 
-    println!("Enter the alphabet: ");
-    io::stdin().read_line(&mut alphabet).expect("Failed to read input.");
-    let alphabet = alphabet.trim();
+pub fn rank_decode(data: &[u8], alphabet: &[u8], variant: MtfVariant) -> Result<Vec<u8>> {
+    let mut list = RankList::new(MtfTable::for_decoding(alphabet)?, variant);
+    let mut output = Vec::with_capacity(data.len());
 
-    let encoded_text = mtf_encode(&input_text.to_string(), &alphabet.to_string());
-    println!("S: {:?}", encoded_text);
-    let decoded_text = mtf_decode(&encoded_text, &alphabet.to_string());
-    println!("M: {}", decoded_text);
+    for &index in data {
+        let byte = list.table.get(index as usize).ok_or(OUTSIDE_ALPHABET)?;
+        output.push(byte);
+        list.promote(index as usize);
+    }
+    Ok(output)
+}
+
+// Inversion frequencies: for every byte value in turn, how many larger bytes come before each
+// of its occurrences since the previous one. The output is the number of occurrences of each
+// value followed by those gaps, all as varints; the largest value fills whatever is left over,
+// so its gaps are not written and the list stops there.
+pub fn inversion_frequencies_encode(data: &[u8]) -> Vec<u8> {
+    let mut counts = [0usize; 256];
+    data.iter().for_each(|&b| counts[b as usize] += 1);
+
+    let mut output = Vec::new();
+    write_varint(&mut output, data.len() as u64);
+    let mut remaining = data.len();
+
+    for symbol in 0..=255u8 {
+        if remaining == 0 {
+            break;
+        }
+        let count = counts[symbol as usize];
+        write_varint(&mut output, count as u64);
+        if count == remaining {
+            break;
+        }
+
+        let mut larger = 0u64;
+        for &byte in data {
+            if byte == symbol {
+                write_varint(&mut output, larger);
+                larger = 0;
+            } else if byte > symbol {
+                larger += 1;
+            }
+        }
+        remaining -= count;
+    }
+    output
+}
+
+pub fn inversion_frequencies_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let malformed = Error::InvalidPayload("malformed inversion frequencies");
+    let mut pos = 0;
+    let len = read_varint(data, &mut pos).ok_or(malformed.clone())?;
+    // Every other byte takes at least one byte of the data to place
+    if len > (data.len() - pos + MAX_FREE_FILL) as u64 {
+        return Err(Error::InvalidHeader("inversion frequencies too long"));
+    }
+    let len = len as usize;
+
+    // Slots still free belong to bytes larger than the ones placed so far
+    let mut output: Vec<Option<u8>> = vec![None; len];
+    let mut remaining = len;
+
+    for symbol in 0..=255u8 {
+        if remaining == 0 {
+            break;
+        }
+        let count = read_varint(data, &mut pos).ok_or(malformed.clone())? as usize;
+        if count > remaining {
+            return Err(malformed);
+        }
+        if count == remaining {
+            output.iter_mut().filter(|slot| slot.is_none()).for_each(|slot| *slot = Some(symbol));
+            remaining = 0;
+            break;
+        }
+
+        let mut slot = 0;
+        for _ in 0..count {
+            let mut skip = read_varint(data, &mut pos).ok_or(malformed.clone())?;
+            loop {
+                match output.get(slot) {
+                    None => return Err(malformed),
+                    Some(Some(_)) => {}
+                    Some(None) if skip == 0 => break,
+                    Some(None) => skip -= 1,
+                }
+                slot += 1;
+            }
+            output[slot] = Some(symbol);
+            slot += 1;
+        }
+        remaining -= count;
+    }
+
+    if remaining > 0 || pos != data.len() {
+        return Err(malformed);
+    }
+    Ok(output.into_iter().map(|slot| slot.unwrap_or_default()).collect())
 }
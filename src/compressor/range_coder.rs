@@ -0,0 +1,269 @@
+// Binary adaptive range coder in the style of LZMA: every byte is coded as 8 binary decisions
+// down a bit tree, each with its own probability that adapts as bits are coded. With order 1
+// the tree is chosen by the previous byte, so a symbol is predicted from the one before it.
+
+use crate::error::{Error, Result};
+
+const PROB_BITS: u32 = 11;
+const PROB_INIT: u16 = 1 << (PROB_BITS - 1);
+// Adaptation speed: a probability moves 1/16 of the way towards every bit it sees
+const MOVE_BITS: u32 = 4;
+const TOP: u32 = 1 << 24;
+
+pub const MAX_ORDER: u8 = 1;
+
+// Move a probability towards the bit just seen
+fn update(prob: &mut u16, bit: bool) {
+    if bit {
+        *prob -= *prob >> MOVE_BITS;
+    } else {
+        *prob += ((1 << PROB_BITS) - *prob) >> MOVE_BITS;
+    }
+}
+
+pub struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    output: Vec<u8>,
+}
+
+impl RangeEncoder {
+    pub fn new() -> Self {
+        RangeEncoder { low: 0, range: u32::MAX, cache: 0, cache_size: 1, output: Vec::new() }
+    }
+
+    // prob is the chance of a 0 bit out of 1 << PROB_BITS, and learns from the bit
+    pub fn encode_bit(&mut self, prob: &mut u16, bit: bool) {
+        self.encode_with(*prob, bit);
+        update(prob, bit);
+    }
+
+    // Code a bit with a probability that is not adapted, such as one mixed from several models
+    pub fn encode_with(&mut self, prob: u16, bit: bool) {
+        let bound = (self.range >> PROB_BITS) * prob as u32;
+        if bit {
+            self.low += bound as u64;
+            self.range -= bound;
+        } else {
+            self.range = bound;
+        }
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    // Bytes are held back while they could still be changed by a carry
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.output.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.output
+    }
+}
+
+impl Default for RangeEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        // The encoder always starts with a zero byte, the carry it never had
+        if data.len() < 5 || data[0] != 0 {
+            return Err(Error::InvalidPayload("invalid range coder data"));
+        }
+        let code = u32::from_be_bytes(data[1..5].try_into().unwrap());
+        Ok(RangeDecoder { data, pos: 5, range: u32::MAX, code })
+    }
+
+    pub fn decode_bit(&mut self, prob: &mut u16) -> Result<bool> {
+        let bit = self.decode_with(*prob)?;
+        update(prob, bit);
+        Ok(bit)
+    }
+
+    pub fn decode_with(&mut self, prob: u16) -> Result<bool> {
+        let bound = (self.range >> PROB_BITS) * prob as u32;
+        let bit = self.code >= bound;
+        if bit {
+            self.code -= bound;
+            self.range -= bound;
+        } else {
+            self.range = bound;
+        }
+        while self.range < TOP {
+            let &byte = self.data.get(self.pos).ok_or(Error::InvalidPayload("truncated range coder data"))?;
+            self.pos += 1;
+            self.range <<= 8;
+            self.code = (self.code << 8) | byte as u32;
+        }
+        Ok(bit)
+    }
+}
+
+// Points of squash(x) = 4096 / (1 + e^-x) for x = -16..=16 in steps of 1/8, as used by lpaq.
+// Mixing works on these integer curves so every machine decodes what any other encoded.
+const SQUASH: [i32; 33] = [
+    1, 2, 3, 6, 10, 16, 27, 45, 73, 120, 194, 310, 488, 747, 1101, 1546, 2047, 2549, 2994, 3348, 3607, 3785, 3901, 3975,
+    4022, 4050, 4068, 4079, 4085, 4089, 4092, 4093, 4094,
+];
+// Mixed probabilities stay this far from certain, so a bit never costs less than about 1/90 bit
+const MIX_LIMIT: i32 = 32;
+const MIX_RATE: i32 = 6;
+const RATE_SHIFT: i32 = 13;
+// Weights stay within 16 either way, which keeps the dot product of two stretched inputs in range
+// however long the input is
+const WEIGHT_LIMIT: i32 = 1 << 20;
+
+// Probability of a 1 out of 4096 for a stretched value d, the log odds times 256
+fn squash(d: i32) -> i32 {
+    let d = d.clamp(-2047, 2047);
+    let (w, i) = (d & 127, ((d >> 7) + 16) as usize);
+    (SQUASH[i] * (128 - w) + SQUASH[i + 1] * w + 64) >> 7
+}
+
+// Bit trees of 255 probabilities: node 1 is the root and node n has children 2n and 2n + 1,
+// so the path to a leaf spells out the byte from its top bit down. Order 0 has one tree; order 1
+// adds a tree per previous byte and mixes both predictions in the logistic domain, with weights
+// per node that learn how far the order-1 tree can be trusted over the order-0 one.
+pub struct ContextModel {
+    order: u8,
+    order0: Vec<u16>,
+    order1: Vec<u16>,
+    previous: u8,
+    // Inverse of squash for every probability, and the mixer weights in 16.16 fixed point
+    stretch: Vec<i32>,
+    weights: Vec<[i32; 2]>,
+    // The stretched inputs and mixed probability of the bit being coded
+    inputs: [i32; 2],
+    mixed: i32,
+}
+
+impl ContextModel {
+    pub fn new(order: u8) -> Self {
+        let mut model = ContextModel {
+            order,
+            order0: vec![PROB_INIT; 256],
+            order1: Vec::new(),
+            previous: 0,
+            stretch: Vec::new(),
+            weights: Vec::new(),
+            inputs: [0; 2],
+            mixed: 0,
+        };
+        if order > 0 {
+            model.order1 = vec![PROB_INIT; 256 * 256];
+            model.weights = vec![[1 << 15; 2]; 256];
+            model.stretch = vec![2047; 4096];
+            let mut next = 0;
+            for d in -2047..=2047 {
+                let p = squash(d) as usize;
+                if p >= next {
+                    model.stretch[next..=p].fill(d);
+                    next = p + 1;
+                }
+            }
+        }
+        model
+    }
+
+    // Chance of a 0 bit at node out of 1 << PROB_BITS
+    fn probability(&mut self, node: usize) -> u16 {
+        if self.order == 0 {
+            return self.order0[node];
+        }
+        // The trees hold 11-bit chances of a 0, the mixer works on 12-bit chances of a 1
+        let order1 = self.order1[self.previous as usize * 256 + node];
+        let one = |prob: u16| ((1 << PROB_BITS) - prob as usize) << 1;
+        self.inputs = [self.stretch[one(self.order0[node])], self.stretch[one(order1)]];
+
+        let [w0, w1] = self.weights[node];
+        let dot = (w0 as i64 * self.inputs[0] as i64 + w1 as i64 * self.inputs[1] as i64) >> 16;
+        self.mixed = squash(dot as i32).clamp(MIX_LIMIT, 4096 - MIX_LIMIT);
+        ((4096 - self.mixed) >> 1) as u16
+    }
+
+    fn learn(&mut self, node: usize, bit: bool) {
+        update(&mut self.order0[node], bit);
+        if self.order > 0 {
+            update(&mut self.order1[self.previous as usize * 256 + node], bit);
+
+            // Move each weight by how much its input would have reduced the error
+            let error = (((bit as i32) << 12) - self.mixed) * MIX_RATE;
+            for (weight, &input) in self.weights[node].iter_mut().zip(&self.inputs) {
+                let step = (input * error + (1 << (RATE_SHIFT - 1))) >> RATE_SHIFT;
+                *weight = (*weight + step).clamp(-WEIGHT_LIMIT, WEIGHT_LIMIT);
+            }
+        }
+    }
+
+    pub fn encode(&mut self, encoder: &mut RangeEncoder, byte: u8) {
+        let mut node = 1;
+        for shift in (0..8).rev() {
+            let bit = (byte >> shift) & 1 == 1;
+            encoder.encode_with(self.probability(node), bit);
+            self.learn(node, bit);
+            node = node * 2 + bit as usize;
+        }
+        self.previous = byte;
+    }
+
+    pub fn decode(&mut self, decoder: &mut RangeDecoder) -> Result<u8> {
+        let mut node = 1;
+        while node < 256 {
+            let bit = decoder.decode_with(self.probability(node))?;
+            self.learn(node, bit);
+            node = node * 2 + bit as usize;
+        }
+        let byte = (node - 256) as u8;
+        self.previous = byte;
+        Ok(byte)
+    }
+}
+
+pub fn range_encode(data: &[u8], order: u8) -> Vec<u8> {
+    let mut model = ContextModel::new(order);
+    let mut encoder = RangeEncoder::new();
+    data.iter().for_each(|&byte| model.encode(&mut encoder, byte));
+    encoder.finish()
+}
+
+pub fn range_decode(data: &[u8], count: usize, order: u8) -> Result<Vec<u8>> {
+    let mut model = ContextModel::new(order);
+    let mut decoder = RangeDecoder::new(data)?;
+    // Even the likeliest byte costs more than 1/16 bit, which bounds what data can hold
+    let mut output = Vec::with_capacity(count.min(data.len().saturating_mul(128)));
+    for _ in 0..count {
+        output.push(model.decode(&mut decoder)?);
+    }
+    Ok(output)
+}
@@ -0,0 +1,133 @@
+// Static rANS: one frequency table, scaled to sum to 1 << SCALE_BITS, codes every byte, so
+// decoding needs nothing but the table and the coded bytes. Only integer arithmetic is used, so
+// the output is the same on every platform. With several ways, byte i goes through state
+// i % ways; the states share one byte stream but do not depend on each other, which lets the
+// CPU work on them in parallel.
+
+use crate::error::{Error, Result};
+
+pub const SCALE_BITS: u32 = 12;
+const SCALE: u32 = 1 << SCALE_BITS;
+// States stay in [LOWER, LOWER << 8) between symbols and move by whole bytes
+const LOWER: u32 = 1 << 23;
+
+pub const INTERLEAVE: usize = 4;
+// A byte that owns every slot costs no bits, so the data cannot bound how many such a table codes
+pub const MAX_FREE_COUNT: usize = 1 << 24;
+
+// Scale byte counts to frequencies summing to 1 << SCALE_BITS, keeping every used byte at 1 or
+// more. All counts zero gives all frequencies zero.
+pub fn normalize_frequencies(counts: &[u32]) -> Vec<u32> {
+    let total: u64 = counts.iter().map(|&count| count as u64).sum();
+    if total == 0 {
+        return vec![0; counts.len()];
+    }
+    let mut freqs: Vec<u32> = counts
+        .iter()
+        .map(|&count| match count {
+            0 => 0,
+            _ => ((count as u64 * SCALE as u64 / total) as u32).max(1),
+        })
+        .collect();
+
+    // Rounding down leaves slots over and raising rare bytes to 1 can take too many; either way
+    // the difference is settled on the most frequent bytes, where it costs the least
+    let mut sum: u32 = freqs.iter().sum();
+    while sum != SCALE {
+        let largest = (0..freqs.len()).max_by_key(|&i| (freqs[i], std::cmp::Reverse(i))).unwrap();
+        if sum < SCALE {
+            freqs[largest] += SCALE - sum;
+            sum = SCALE;
+        } else {
+            let take = (sum - SCALE).min(freqs[largest] - 1);
+            freqs[largest] -= take;
+            sum -= take;
+        }
+    }
+    freqs
+}
+
+fn cumulative(freqs: &[u32]) -> Vec<u32> {
+    freqs
+        .iter()
+        .scan(0, |start, &freq| {
+            let cum = *start;
+            *start += freq;
+            Some(cum)
+        })
+        .collect()
+}
+
+// freqs comes from normalize_frequencies and must give every byte of data a frequency
+pub fn rans_encode(data: &[u8], freqs: &[u32], ways: usize) -> Vec<u8> {
+    let cum = cumulative(freqs);
+    let mut states = vec![LOWER; ways];
+    // rANS works like a stack: bytes are coded last to first and the output is written
+    // backwards, so the decoder reads it forwards and gets the bytes first to last
+    let mut output = Vec::with_capacity(data.len() / 2 + 4 * ways);
+    for (i, &byte) in data.iter().enumerate().rev() {
+        let (freq, start) = (freqs[byte as usize], cum[byte as usize]);
+        let state = &mut states[i % ways];
+        let limit = ((LOWER >> SCALE_BITS) << 8) * freq;
+        while *state >= limit {
+            output.push(*state as u8);
+            *state >>= 8;
+        }
+        *state = ((*state / freq) << SCALE_BITS) + *state % freq + start;
+    }
+    for state in states.iter().rev() {
+        output.extend(state.to_le_bytes());
+    }
+    output.reverse();
+    output
+}
+
+pub fn rans_decode(data: &[u8], freqs: &[u32], count: usize, ways: usize) -> Result<Vec<u8>> {
+    if freqs.len() > 256 || freqs.iter().map(|&freq| freq as u64).sum::<u64>() != SCALE as u64 {
+        return Err(Error::InvalidHeader("invalid rANS frequencies"));
+    }
+    let cum = cumulative(freqs);
+    let mut slots = vec![0u8; SCALE as usize];
+    for (byte, (&freq, &start)) in freqs.iter().zip(&cum).enumerate() {
+        slots[start as usize..(start + freq) as usize].fill(byte as u8);
+    }
+
+    let truncated = Error::InvalidPayload("truncated rANS data");
+    let head = data.get(..4 * ways).ok_or(truncated.clone())?;
+    let mut states: Vec<u32> = head.chunks(4).map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap())).collect();
+    if states.iter().any(|&state| !(LOWER..LOWER << 8).contains(&state)) {
+        return Err(Error::InvalidPayload("invalid rANS state"));
+    }
+
+    // Every other byte takes at least (SCALE - freq) / SCALE bits of the states and the bytes after
+    // them, so the count cannot be more than that allows
+    let largest = freqs.iter().copied().max().unwrap_or(0) as u64;
+    let limit = match SCALE as u64 - largest {
+        0 => MAX_FREE_COUNT as u64,
+        spare => (data.len() as u64 * 8 * SCALE as u64).div_ceil(spare),
+    };
+    if count as u64 > limit {
+        return Err(Error::InvalidHeader("rANS symbol count too large"));
+    }
+
+    let mut pos = 4 * ways;
+    let mut output = Vec::with_capacity(count);
+    for i in 0..count {
+        let state = &mut states[i % ways];
+        let slot = *state & (SCALE - 1);
+        let byte = slots[slot as usize];
+        *state = freqs[byte as usize] * (*state >> SCALE_BITS) + slot - cum[byte as usize];
+        while *state < LOWER {
+            let &next = data.get(pos).ok_or(truncated.clone())?;
+            pos += 1;
+            *state = (*state << 8) | next as u32;
+        }
+        output.push(byte);
+    }
+
+    // The encoder started every state at LOWER and used up all its bytes
+    if pos != data.len() || states.iter().any(|&state| state != LOWER) {
+        return Err(Error::InvalidPayload("corrupt rANS data"));
+    }
+    Ok(output)
+}
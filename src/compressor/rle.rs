@@ -1,63 +1,212 @@
-use std::io;
+use crate::error::{Error, Result};
 
-pub fn rle_encode(text: &Vec<u8>) -> Vec<u8> {
-    let mut n_zero: u8 = 0;
-    let mut l: Vec<u8> = Vec::new();
+// Run digits of a zero run, every other value v is stored as v + 2
+pub const RUNA: u16 = 0;
+pub const RUNB: u16 = 1;
+// Largest symbol rle_encode emits, for byte 255
+pub const MAX_SYMBOL: u16 = 257;
+// A zero run takes a digit per doubling, so a few symbols could otherwise decode into gigabytes.
+// Programs run about a hundred times longer than their message, so the default pipeline still
+// takes messages of about ten MB.
+pub const MAX_DECODED_LEN: usize = 1 << 30;
+
+pub fn rle_encode(text: &[u8]) -> Vec<u16> {
+    let mut n_zero: usize = 0;
+    let mut l: Vec<u16> = Vec::new();
     let s = text;
-    
+
     for i in 0..s.len() + 1 {
         let current = s.get(i).cloned();            // Get the current element from the input vector
         if i < s.len() && current == Some(0) {
             n_zero += 1;                            // Count consecutive zeros
         } else {
-            let n_z = n_zero + 1;
-            let binary = format!("{:b}", n_z);      // Convert the count to binary representation
-            let binary_without_msb = binary.chars().skip(1).collect::<String>();                // Remove the most significant bit
-            l.extend(binary_without_msb.chars().rev().filter_map(|num| num.to_digit(2).map(|digit| digit as u8)));  // Add each binary digit to the output vector
+            push_run(&mut l, n_zero);
             n_zero = 0;
-            l.extend(current.into_iter().map(|current_value| current_value.wrapping_add(2)));   // Add the current value plus 2 to the output vector
+            l.extend(current.map(|current_value| current_value as u16 + 2));   // Add the current value plus 2 to the output vector
         }
     }
     l
 }
 
-pub fn rle_decode(data: &Vec<u8>) -> Vec<u8> {
-    let mut n_zero: Vec<u8> = Vec::new();
+// A run of n zeros in bijective base 2, least significant digit first: the binary form of
+// n + 1 without its most significant bit, so RUNA is worth 1 and RUNB 2 at every position
+fn push_run(l: &mut Vec<u16>, n_zero: usize) {
+    let mut n_z = n_zero as u128 + 1;
+    while n_z > 1 {
+        l.push((n_z & 1) as u16);
+        n_z >>= 1;
+    }
+}
+
+pub fn rle_decode(data: &[u16]) -> Result<Vec<u8>> {
     let mut s: Vec<u8> = Vec::new();
-    let l = data;
+    let (mut n_zero, mut weight): (usize, usize) = (0, 1);
 
-    for i in 0..l.len() + 1 {
-        let current = l.get(i).cloned();               // Get the current element from the encoded vector
-        if i <= l.len() - 1 && (current.map_or(false, |value| value == 0 || value == 1)) {
-            n_zero.push(current.unwrap());
-        } else {
-            let n_z = n_zero.iter().rev().fold(1, |acc, &bit| (acc << 1) | bit);    // Convert the zero values to a count
-            s.extend(std::iter::repeat(0).take((n_z - 1) as usize));                // Add the corresponding number of zeros to the output vector
-            n_zero.clear();
-            if let Some(current_value) = current {
-                s.push(current_value.wrapping_sub(2));  // Add the current value minus 2 to the output vector
+    for i in 0..data.len() + 1 {
+        let current = data.get(i).cloned();             // Get the current element from the encoded vector
+        if let Some(symbol @ (RUNA | RUNB)) = current {
+            // Add 1 or 2 times the weight of this digit, runs past MAX_DECODED_LEN are rejected
+            let digit = weight.checked_mul(symbol as usize + 1);
+            n_zero = digit
+                .and_then(|digit| n_zero.checked_add(digit))
+                .filter(|&n_zero| s.len() + n_zero <= MAX_DECODED_LEN)
+                .ok_or(Error::InvalidPayload("RLE zero run too long"))?;
+            weight = weight.saturating_mul(2);
+            continue;
+        }
+
+        if n_zero > 0 {
+            s.extend(std::iter::repeat_n(0, n_zero));   // Add the corresponding number of zeros to the output vector
+            (n_zero, weight) = (0, 1);
+        }
+        match current {
+            Some(symbol @ 3..=MAX_SYMBOL) => s.push((symbol - 2) as u8),    // Add the current value minus 2 to the output vector
+            Some(_) => return Err(Error::InvalidPayload("invalid RLE symbol")),
+            None => {}
+        }
+    }
+    Ok(s)
+}
+
+// PackBits packets: a header h below 128 is followed by h + 1 literal bytes, a header above
+// 128 by one byte repeated 257 - h times. 128 is never written and skipped when read.
+pub fn packbits_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + data.len() / 128 + 1);
+    let mut i = 0;
+
+    while i < data.len() {
+        let run = data[i..].iter().take(128).take_while(|&&b| b == data[i]).count();
+        if run >= 2 {
+            output.push((257 - run) as u8);
+            output.push(data[i]);
+            i += run;
+            continue;
+        }
+
+        // Literals up to the next run of three, a pair inside literals costs no more than a run
+        let start = i;
+        while i < data.len() && i - start < 128 && !data[i..].starts_with(&[data[i]; 3]) {
+            i += 1;
+        }
+        output.push((i - start - 1) as u8);
+        output.extend_from_slice(&data[start..i]);
+    }
+    output
+}
+
+pub fn packbits_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+
+    while i < data.len() {
+        let header = data[i] as usize;
+        i += 1;
+        match header {
+            0..=127 => {
+                let literals = data.get(i..i + header + 1).ok_or(Error::InvalidPayload("truncated PackBits literals"))?;
+                output.extend_from_slice(literals);
+                i += header + 1;
+            }
+            128 => {}
+            _ => {
+                let &byte = data.get(i).ok_or(Error::InvalidPayload("truncated PackBits run"))?;
+                output.extend(std::iter::repeat_n(byte, 257 - header));
+                i += 1;
             }
         }
     }
-    s
+    Ok(output)
 }
 
-fn main() {
-    let mut input = String::new();
-    println!("Enter a vector (e.g., [3, 3, 4, 1, 1, 1, 0, 0, 4, 4]): ");
-    io::stdin().read_line(&mut input).expect("Failed to read input.");
+// bzip2's initial RLE: a run of 4 to 255 equal bytes becomes the first 4 and a count of the rest
+pub fn rle1_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut i = 0;
 
-    let sequence: Vec<u8> = input
-        .trim()
-        .trim_start_matches('[')
-        .trim_end_matches(']')
-        .split(',')
-        .map(|s| s.trim().parse().expect("Invalid number"))
-        .collect();
+    while i < data.len() {
+        let byte = data[i];
+        let run = data[i..].iter().take(255).take_while(|&&b| b == byte).count();
+        if run >= 4 {
+            output.extend([byte; 4]);
+            output.push((run - 4) as u8);
+        } else {
+            output.extend(std::iter::repeat_n(byte, run));
+        }
+        i += run;
+    }
+    output
+}
+
+// Every count is valid, and like bzip2 a block may end straight after four equal bytes
+pub fn rle1_decode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let (mut last, mut run) = (None, 0);
+
+    for &byte in data {
+        if run == 4 {
+            output.extend(std::iter::repeat_n(last.unwrap_or_default(), byte as usize));
+            run = 0;
+            continue;
+        }
+        if last == Some(byte) {
+            run += 1;
+        } else {
+            last = Some(byte);
+            run = 1;
+        }
+        output.push(byte);
+    }
+    output
+}
+
+// Command runs of a diropql program: bytes below 0x80 stand for themselves, 0x80 to 0xfe repeat
+// the previous byte 1 to 127 more times, and 0xff escapes the byte after it. A program is all
+// letters, so its runs of increments shrink to a letter and a count token or two.
+const COMMAND_RUN_MIN: u8 = 0x80;
+const COMMAND_RUN_MAX: usize = 127;
+const COMMAND_ESCAPE: u8 = 0xff;
 
-    let encoded_sequence = rle_encode(&sequence.iter().map(|&x| x as u8).collect());
-    println!("Encoded sequence: {:?}", encoded_sequence);
+pub fn command_runs_encode(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() / 4);
+    let mut i = 0;
 
-    let decoded_sequence = rle_decode(&encoded_sequence);
-    println!("Decoded sequence: {:?}", decoded_sequence);
+    while i < data.len() {
+        let byte = data[i];
+        if byte >= COMMAND_RUN_MIN {
+            output.push(COMMAND_ESCAPE);
+        }
+        output.push(byte);
+
+        let mut run = data[i + 1..].iter().take_while(|&&b| b == byte).count();
+        i += run + 1;
+        while run > 0 {
+            let repeat = run.min(COMMAND_RUN_MAX);
+            output.push(COMMAND_RUN_MIN + repeat as u8 - 1);
+            run -= repeat;
+        }
+    }
+    output
+}
+
+pub fn command_runs_decode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len() * 4);
+    let mut i = 0;
+
+    while i < data.len() {
+        let byte = data[i];
+        i += 1;
+        match byte {
+            COMMAND_ESCAPE => {
+                let &escaped = data.get(i).ok_or(Error::InvalidPayload("truncated command-run escape"))?;
+                output.push(escaped);
+                i += 1;
+            }
+            COMMAND_RUN_MIN.. => {
+                let &last = output.last().ok_or(Error::InvalidPayload("command run with nothing to repeat"))?;
+                output.extend(std::iter::repeat_n(last, (byte - COMMAND_RUN_MIN) as usize + 1));
+            }
+            _ => output.push(byte),
+        }
+    }
+    Ok(output)
 }
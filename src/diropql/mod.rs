@@ -1 +1,4 @@
 pub mod zip;
+pub mod search;
+pub mod stream;
+pub mod stego;
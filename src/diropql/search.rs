@@ -0,0 +1,76 @@
+use crate::compressor::fm_index::FmIndex;
+use crate::error::{Error, Result};
+use crate::pipeline::{bwt_blocks, BWT_ID, DIROPQL_ID};
+
+use super::zip::read_meta;
+
+// Where a pattern occurs in one blob, and the length of the message or program searched
+#[derive(Debug)]
+pub struct DpqlzSearch {
+    pub len: usize,
+    pub offsets: Vec<usize>,
+}
+
+// The diropql text of pattern as it appears inside a program: the 'r' every byte starts
+// with, then its increments and output. The leading 'r' pins the run of increments to
+// its full length, so every match lines up with a byte of the message.
+fn program_pattern(pattern: &[u8]) -> Vec<u8> {
+    let mut program = vec![b'r'];
+    for (i, &byte) in pattern.iter().enumerate() {
+        if i > 0 {
+            program.push(b'r');
+        }
+        program.extend(std::iter::repeat_n(b'i', byte as usize));
+        program.push(b'o');
+    }
+    program
+}
+
+// Find pattern in the message of a blob using an FM-index over its stored BWT blocks: only the
+// stages after the BWT are undone, the BWT and diropql stages never are. The pipeline has to be
+// a classic BWT, optionally after diropql. Matches that straddle two BWT blocks are not found,
+// nor are bytes whose cell the program clears first, which only happens past MEMORY_SIZE bytes.
+pub fn search_diropqlz(blob: &str, pattern: &[u8]) -> Result<DpqlzSearch> {
+    search(blob, pattern, false)
+}
+
+// Same search over the diropql program itself, offsets and length count program bytes
+pub fn search_diropqlz_program(blob: &str, pattern: &[u8]) -> Result<DpqlzSearch> {
+    search(blob, pattern, true)
+}
+
+fn search(blob: &str, pattern: &[u8], in_program: bool) -> Result<DpqlzSearch> {
+    let (meta, payload) = read_meta(blob)?;
+    let stages = meta.pipeline.stages();
+
+    let bwt = stages.iter().position(|stage| stage.id() == BWT_ID);
+    let has_program = match bwt {
+        Some(0) => false,
+        Some(1) if stages[0].id() == DIROPQL_ID => true,
+        _ => return Err(Error::InvalidPipeline("only pipelines starting with bwt or diropql|bwt can be searched".to_string())),
+    };
+    if in_program && !has_program {
+        return Err(Error::InvalidPipeline("the blob holds no diropql program".to_string()));
+    }
+    let bwt = bwt.unwrap();
+
+    let transformed = meta.pipeline.decode_to(&payload, &meta.params, bwt)?;
+    let blocks = bwt_blocks(&transformed, &meta.params[bwt])?;
+
+    // Message offsets in a program are the number of 'o' commands before the match
+    let by_separators = has_program && !in_program;
+    let needle = if by_separators { program_pattern(pattern) } else { pattern.to_vec() };
+
+    let (mut offsets, mut len) = (Vec::new(), 0);
+    for (block, index) in blocks {
+        let fm_index = FmIndex::new(block, index, b'o');
+
+        if !pattern.is_empty() {
+            let hits = fm_index.locate(&needle);
+            offsets.extend(hits.iter().map(|hit| len + if by_separators { hit.separators } else { hit.offset }));
+        }
+        // The sentinel is not part of the block
+        len += if by_separators { fm_index.separators() } else { fm_index.len() - 1 };
+    }
+    Ok(DpqlzSearch { len, offsets })
+}
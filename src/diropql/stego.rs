@@ -0,0 +1,94 @@
+// Hides a diropqlz stream in a cover text, so that the message is not only unreadable but goes
+// unnoticed. Every byte of the stream becomes eight bits, most significant first, written either
+// as zero-width characters between the words of the cover or as spaces and tabs at the ends of
+// its lines. Neither changes how the cover looks.
+
+use crate::error::{Error, Result};
+
+// Zero-width space for a 0 bit, zero-width non-joiner for a 1 bit
+const ZERO_WIDTH: [char; 2] = ['\u{200b}', '\u{200c}'];
+const WHITESPACE: [char; 2] = [' ', '\t'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Carrier {
+    // Invisible characters after the spaces of the cover, which survive most copy and paste
+    #[default]
+    ZeroWidth,
+    // Trailing whitespace, which survives channels that strip unusual characters but not editors
+    // that trim lines
+    Whitespace,
+}
+
+impl Carrier {
+    pub const ALL: [Carrier; 2] = [Carrier::ZeroWidth, Carrier::Whitespace];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Carrier::ZeroWidth => "zero-width",
+            Carrier::Whitespace => "whitespace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Carrier> {
+        Carrier::ALL.into_iter().find(|carrier| carrier.name() == name)
+    }
+}
+
+fn to_bits(payload: &str) -> impl Iterator<Item = usize> + '_ {
+    payload.bytes().flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) as usize & 1))
+}
+
+// Trailing whitespace of the cover, and any zero-width characters it has, are dropped, as they would
+// be read back as part of the payload
+pub fn hide(payload: &str, cover: &str, carrier: Carrier) -> String {
+    let cover: String = cover.chars().filter(|c| !ZERO_WIDTH.contains(c)).collect();
+    let mut bits = to_bits(payload);
+    let total = payload.len() * 8;
+    let mut out = String::with_capacity(cover.len() + total * 3);
+
+    match carrier {
+        // The bits are spread evenly over the spaces, whatever is left over goes at the end
+        Carrier::ZeroWidth => {
+            let per_gap = total.div_ceil(cover.matches(' ').count().max(1));
+            for c in cover.chars() {
+                out.push(c);
+                if c == ' ' {
+                    out.extend(bits.by_ref().take(per_gap).map(|bit| ZERO_WIDTH[bit]));
+                }
+            }
+            out.extend(bits.map(|bit| ZERO_WIDTH[bit]));
+        }
+        Carrier::Whitespace => {
+            let lines: Vec<&str> = cover.lines().collect();
+            let per_line = total.div_ceil(lines.len().max(1));
+            for line in lines.iter().copied().chain(lines.is_empty().then_some("")) {
+                out.push_str(line.trim_end());
+                out.extend(bits.by_ref().take(per_line).map(|bit| WHITESPACE[bit]));
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+// The carrier is told by what the text holds: zero-width characters if there are any, otherwise
+// the trailing whitespace of its lines
+pub fn reveal(text: &str) -> Result<String> {
+    let mut bits: Vec<u8> =
+        text.chars().filter_map(|c| ZERO_WIDTH.iter().position(|&z| z == c)).map(|bit| bit as u8).collect();
+    if bits.is_empty() {
+        for line in text.lines() {
+            let trailing = line.len() - line.trim_end_matches(WHITESPACE).len();
+            bits.extend(line[line.len() - trailing..].chars().map(|c| (c == WHITESPACE[1]) as u8));
+        }
+    }
+
+    if bits.is_empty() {
+        return Err(Error::InvalidCover("no hidden payload"));
+    }
+    if !bits.len().is_multiple_of(8) {
+        return Err(Error::InvalidCover("hidden bits do not make whole bytes"));
+    }
+    let bytes = bits.chunks(8).map(|byte| byte.iter().fold(0u8, |value, &bit| (value << 1) | bit)).collect();
+    String::from_utf8(bytes).map_err(|_| Error::InvalidCover("hidden payload is not text"))
+}
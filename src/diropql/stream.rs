@@ -0,0 +1,175 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use super::zip::{read_diropqlz_bytes, write_diropqlz_with, MEMORY_SIZE};
+use crate::pipeline::{Pipeline, MAGIC_PREFIX};
+
+// A block that fits in diropql memory gives every byte a fresh cell, so its program never has to
+// clear one and stays searchable; the program generator handles longer input on its own
+pub const MAX_BLOCK_SIZE: usize = MEMORY_SIZE - 1;
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+// Obfuscates everything written to it block by block, emitting one diropqlz blob per line
+pub struct DiropqlzWriter<W: Write> {
+    inner: Option<W>,
+    block: Vec<u8>,
+    block_size: usize,
+    pipeline: Pipeline,
+}
+
+impl<W: Write> DiropqlzWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_block_size(inner, DEFAULT_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(inner: W, block_size: usize) -> Self {
+        Self::with_pipeline(inner, Pipeline::default(), block_size)
+    }
+
+    pub fn with_pipeline(inner: W, pipeline: Pipeline, block_size: usize) -> Self {
+        let block_size = block_size.clamp(1, MAX_BLOCK_SIZE);
+        DiropqlzWriter {
+            inner: Some(inner),
+            block: Vec::with_capacity(block_size),
+            block_size,
+            pipeline,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    // Encode whatever is buffered, even if the block is not full yet
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+
+        let blob = write_diropqlz_with(&self.block, &self.pipeline).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let inner = self.inner.as_mut().unwrap();
+        inner.write_all(blob.as_bytes())?;
+        inner.write_all(b"\n")?;
+        self.block.clear();
+        Ok(())
+    }
+
+    // Write the last partial block and hand back the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_block()?;
+        self.inner.as_mut().unwrap().flush()?;
+        Ok(self.inner.take().unwrap())
+    }
+}
+
+impl<W: Write> Write for DiropqlzWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.block_size - self.block.len());
+        self.block.extend_from_slice(&buf[..n]);
+
+        if self.block.len() == self.block_size {
+            self.write_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for DiropqlzWriter<W> {
+    fn drop(&mut self) {
+        // Errors cannot be reported from drop, call finish() to see them
+        if self.inner.is_some() {
+            let _ = self.write_block();
+        }
+    }
+}
+
+// Blobs of a stream, one per line. Only a blob's first line starts with the magic, so the lines
+// of an armor that wraps them are joined back onto it, keeping the breaks that separate words.
+pub fn split_blobs(input: &str) -> Vec<String> {
+    let mut blobs: Vec<String> = Vec::new();
+    for line in input.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match blobs.last_mut() {
+            Some(blob) if !line.starts_with(MAGIC_PREFIX) => {
+                blob.push('\n');
+                blob.push_str(line)
+            }
+            _ => blobs.push(line.to_string()),
+        }
+    }
+    blobs
+}
+
+// Reads a stream of diropqlz blobs, one per line or wrapped over several, and yields the
+// recovered bytes
+pub struct DiropqlzReader<R: Read> {
+    inner: BufReader<R>,
+    line: String,
+    // The blob read so far; it is complete once the next one starts or the stream ends
+    pending: String,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> DiropqlzReader<R> {
+    pub fn new(inner: R) -> Self {
+        DiropqlzReader {
+            inner: BufReader::new(inner),
+            line: String::new(),
+            pending: String::new(),
+            block: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+
+    // Decode the next blob, returns false at the end of the stream
+    fn read_block(&mut self) -> io::Result<bool> {
+        let blob = loop {
+            self.line.clear();
+            if self.inner.read_line(&mut self.line)? == 0 {
+                if self.pending.is_empty() {
+                    return Ok(false);
+                }
+                break std::mem::take(&mut self.pending);
+            }
+
+            let line = self.line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with(MAGIC_PREFIX) && !self.pending.is_empty() {
+                break std::mem::replace(&mut self.pending, line.to_string());
+            }
+            if !self.pending.is_empty() {
+                self.pending.push('\n');
+            }
+            self.pending.push_str(line);
+        };
+
+        self.block = read_diropqlz_bytes(&blob).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DiropqlzReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.block.len() {
+            if !self.read_block()? {
+                return Ok(0);
+            }
+        }
+
+        let n = buf.len().min(self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
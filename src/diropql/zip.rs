@@ -1,229 +1,214 @@
-use crate::compressor::bwt::{bwt_encode, bwt_decode};
-use crate::compressor::mtf::{mtf_encode, mtf_decode};
-use crate::compressor::rle::{rle_encode, rle_decode};
-//use crate::compressor::huffman::{huffman_encode, huffman_decode};
-use base85::{encode, decode};
+use crate::compressor::huffman::{canonical_codebook_from_lengths, codebook_entries, CodeEntry};
+use crate::error::{Error, Result};
+use crate::pipeline::{
+    huffman_code_lengths, read_varint, write_varint, Armor, Pipeline, HUFFMAN_ID, HUFFMAN_TABLES_ID,
+};
+pub const MEMORY_SIZE: usize = 10000;
 
-static ALPHABET: &str = "diropql";
-const MEMORY_SIZE: usize = 10000;
-
-fn compress(program: &String) -> (Vec<u8>, usize) {
-
-    let (compressed_program, index) = bwt_encode(program);
-    //println!("AFTER BWT ENCODING: {}", compressed_program);
-
-    let compressed_program = mtf_encode(&program, ALPHABET);
-    //println!("AFTER MTF ENCODING: {:?}", compressed_program);
-
-    let compressed_program = rle_encode(&compressed_program);
-    //println!("AFTER RLE ENCODING: {:?}", compressed_program);
-
-    // let mut compressed_program = encode_huffman(rle_program);
-    // println!("AFTER HUFFMAN ENCODING: {}", compressed_program);
-
-    (compressed_program, index)
-}
-
-fn decompress(program: &Vec<u8>, meta: &DpqlzMeta, _alphabet: &str) -> String {
-//fn decompress(program: &String, index: &u64) -> String {
-
-    // let mut decoded_huff_program = decode_huffman(program, );
-
-    let decoded_program = rle_decode(program);
-    //println!("AFTER RLE DECODING: {:?}", decoded_program);
-
-    let decoded_program = mtf_decode(&decoded_program, &ALPHABET);
-    //println!("AFTER MTF DECODING: {}", decoded_program);
-
-    let decoded_program_bwt = bwt_decode(&decoded_program, (meta.bwt_idx as usize));
-    //println!("AFTER BWT DECODING: {}", decoded_program);
+// MODULE DIROPQRL
 
-    decoded_program
+pub fn write_diropql(text: &str) -> String {
+    write_diropql_bytes(text.as_bytes())
 }
 
-// MODULE DIROPQRL
+pub fn write_diropql_bytes(data: &[u8]) -> String {
 
-pub fn write_diropql(text: &str) -> String {
-    
     let mut program = String::new();
 
     // Set memory pointer to the first cell
-    program.push_str("r");
+    program.push('r');
 
-    for c in text.chars() {
-        let ascii_val = c as u8;
+    for (i, &byte) in data.iter().enumerate() {
+        // Past MEMORY_SIZE bytes the pointer has wrapped around, so clear what the cell still holds
+        if i >= MEMORY_SIZE && data[i - MEMORY_SIZE] != 0 {
+            program.push_str("pdq");
+        }
 
-        // Increment the current memory cell by the ASCII value
-        program.push_str(&format!("{}", "i".repeat(ascii_val as usize)));
+        // Increment the current memory cell by the byte value
+        program.push_str(&"i".repeat(byte as usize));
 
         // Output the value in the current memory cell
-        program.push_str("o");
+        program.push('o');
 
         // Set memory pointer to the next cell
-        program.push_str(&format!("{}", "r"));
+        program.push('r');
     }
 
     program
 
 }
 
-pub fn read_diropql(prog: &str) -> String {
+// Pair every loop command with its partner so jumps are a single lookup
+fn match_loops(prog: &[u8]) -> Result<Vec<usize>> {
+    let mut jumps = vec![0usize; prog.len()];
+    let mut open = Vec::new();
+
+    for (ip, &command) in prog.iter().enumerate() {
+        match command {
+            b'p' => open.push(ip),
+            b'q' => {
+                let start = open.pop().ok_or(Error::InvalidProgram("unmatched 'q'"))?;
+                jumps[start] = ip;
+                jumps[ip] = start;
+            }
+            _ => {}
+        }
+    }
+
+    if !open.is_empty() {
+        return Err(Error::InvalidProgram("unmatched 'p'"));
+    }
+    Ok(jumps)
+}
+
+pub fn read_diropql(prog: &str) -> Result<String> {
+    read_diropql_bytes(prog).map(|output| String::from_utf8_lossy(&output).into_owned())
+}
+
+pub fn read_diropql_bytes(prog: &str) -> Result<Vec<u8>> {
+    let prog = prog.as_bytes();
+    let jumps = match_loops(prog)?;
     let mut memory = vec![0u8; MEMORY_SIZE];
     let mut mp = 0usize;
     let mut ip = 0usize;
-    let mut oq = String::new();
+    let mut oq = Vec::new();
 
     while ip < prog.len() {
-        let command = prog.chars().nth(ip).unwrap();
-
-        match command {
-            'l' => mp = (mp + MEMORY_SIZE - 1) % MEMORY_SIZE,
-            'r' => mp = (mp + 1) % MEMORY_SIZE,
-            'i' => memory[mp] = memory[mp].wrapping_add(1),
-            'd' => memory[mp] = memory[mp].wrapping_sub(1),
-            'o' => oq.push(memory[mp] as char),
-            'p' => {
-                if memory[mp] == 0 {
-                    let mut count = 1;
-                    while count > 0 {
-                        ip += 1;
-                        if prog.chars().nth(ip).unwrap() == 'p' {
-                            count += 1;
-                        } else if prog.chars().nth(ip).unwrap() == 'q' {
-                            count -= 1;
-                        }
-                    }
-                }
-            }
-            'q' => {
-                if memory[mp] != 0 {
-                    let mut count = 1;
-                    while count > 0 {
-                        ip -= 1;
-                        if prog.chars().nth(ip).unwrap() == 'q' {
-                            count += 1;
-                        } else if prog.chars().nth(ip).unwrap() == 'p' {
-                            count -= 1;
-                        }
-                    }
-                }
-            }
+        match prog[ip] {
+            b'l' => mp = (mp + MEMORY_SIZE - 1) % MEMORY_SIZE,
+            b'r' => mp = (mp + 1) % MEMORY_SIZE,
+            b'i' => memory[mp] = memory[mp].wrapping_add(1),
+            b'd' => memory[mp] = memory[mp].wrapping_sub(1),
+            b'o' => oq.push(memory[mp]),
+            b'p' if memory[mp] == 0 => ip = jumps[ip],
+            b'q' if memory[mp] != 0 => ip = jumps[ip],
             _ => {}
         }
         ip += 1;
     }
-    oq
+    Ok(oq)
 }
 
 // MODULE ZIP
 
+#[derive(Debug)]
 pub struct DpqlzMeta {
     pub mlen: u64,
-    pub moffset: u8,
-    pub bwt_idx: u64,
-    //pub huffman_tree: Vec<u8>,
+    pub pipeline: Pipeline,
+    pub params: Vec<Vec<u8>>,
 }
 
-pub fn write_diropqlz(text: &String) -> String {
-
-    let diropql_program = write_diropql(text);
-    let (compressed_program, index) = compress(&diropql_program);
-    let offset = compressed_program.len() % 8;
-
-    //println!("MLEN BEFORE DECODING: {}", compressed_program.len());
-    //println!("MOFFSET BEFORE DECODING: {}", offset);
-    //println!("BWT INDEX BEFORE DECODING: {}", index);
-
-    let meta = DpqlzMeta {
-        mlen: compressed_program.len() as u64,
-        moffset: offset as u8,
-        bwt_idx: index as u64,
-        // huffman_tree: vec![0; 16],
-    };
+pub fn write_diropqlz(text: &str) -> String {
+    write_diropqlz_bytes(text.as_bytes())
+}
 
-    let diropqlz_program = write_meta(&meta, compressed_program);
-    diropqlz_program
+pub fn write_diropqlz_bytes(data: &[u8]) -> String {
+    write_diropqlz_with(data, &Pipeline::default()).expect("the default pipeline accepts any message up to about 10 MB")
 }
 
-pub fn write_meta(meta: &DpqlzMeta, program: Vec<u8>) -> String {
+pub fn write_diropqlz_with(data: &[u8], pipeline: &Pipeline) -> Result<String> {
 
-    let mut output = String::new();
+    let (compressed_program, params) = pipeline.encode(data)?;
 
-    // Convert meta into the diropqlz metadata layout
-    let obfuscated_length = ((program.len() as f64 * 8.0).ceil() / 8.0) as u64;
+    Ok(write_meta(pipeline, &params, compressed_program))
+}
 
-    output.push_str(&obfuscated_length.to_string());
-    output.push_str(&meta.moffset.to_string());
-    output.push_str(&meta.bwt_idx.to_string());
-    // output.push_str(&huffman_tree);
+pub fn write_meta(pipeline: &Pipeline, params: &[Vec<u8>], program: Vec<u8>) -> String {
 
-    let str_program = String::from_utf8(program).unwrap();
-    output.push_str(&str_program);
+    let mut output = Vec::new();
 
-    //println!("BEFORE BASE85 ENCODING: {}", output);
+    // The header records every stage with its parameters, followed by the program length
+    pipeline.write_header(params, &mut output);
+    write_varint(&mut output, program.len() as u64);
 
-    let compressed_program = output.as_bytes();
-    let mprime = encode(compressed_program);
-    let diropqrlz_file = "DIROPQLZ".to_string() + &mprime;
+    output.extend(program);
 
-    diropqrlz_file
+    let armor = pipeline.armor();
+    armor.magic() + &armor.encode(&output)
 }
 
-pub fn read_diropqlz(program: &String) -> String {
-
-    // Remove the magic string
-    let program = program[8..].to_string();
-
-    let (meta, diropqlz_program) = read_meta(&program);
-    let decompressed_program = decompress(&diropqlz_program, &meta, &ALPHABET);
-    //let decompressed_program = decompress(&diropqlz_program, &meta.bwt_idx);
+pub fn read_diropqlz(program: &str) -> Result<String> {
+    read_diropqlz_bytes(program).map(|message| String::from_utf8_lossy(&message).into_owned())
+}
 
-    //println!("HUF BITLENS AFTER DECODING: {:?}", meta.huf_bitlens);
+pub fn read_diropqlz_bytes(program: &str) -> Result<Vec<u8>> {
 
-    let diropql_program = read_diropql(&decompressed_program);
+    let (meta, diropqlz_program) = read_meta(program)?;
 
-    diropql_program
+    meta.pipeline.decode(&diropqlz_program, &meta.params)
 }
 
-pub fn read_meta(program: &String) -> (DpqlzMeta, Vec<u8>) {
+pub fn read_meta(program: &str) -> Result<(DpqlzMeta, Vec<u8>)> {
 
-    let mprime = decode(program).unwrap();
+    // Remove the magic string, whose last letter names the armor
+    let (armor, program) = Armor::detect(program)?;
 
-    let compressed_program = std::str::from_utf8(&mprime).unwrap();
-    let compressed_program_bytes = compressed_program.as_bytes();
-
-    //println!("AFTER BASE85 DECODING: {}", compressed_program);
+    let mprime = armor.decode(program)?;
 
     // Extract the metadata values
-    let length_bytes = &compressed_program_bytes[..3];
-    let mlen = std::str::from_utf8(&length_bytes).unwrap();
-    let mlen_u64: u64 = mlen.parse().unwrap();
+    let mut pos = 0;
+    let (pipeline, params) = Pipeline::read_header(&mprime, &mut pos)?;
+    let pipeline = pipeline.with_armor(armor);
+    let mlen = read_varint(&mprime, &mut pos).ok_or(Error::InvalidHeader("missing length"))?;
+    let obfuscated_bytes = &mprime[pos..];
+
+    if obfuscated_bytes.len() as u64 != mlen {
+        return Err(Error::InvalidHeader("length does not match the program"));
+    }
 
-    let ignore_bits_byte = compressed_program_bytes[3];
-    let binding = [ignore_bits_byte];
-    let moffset = std::str::from_utf8(&binding).unwrap();
-    let moffset_u8: u8 = moffset.parse().unwrap();
+    let meta = DpqlzMeta {
+        mlen,
+        pipeline,
+        params,
+    };
 
-    let index_bytes = &compressed_program_bytes[4..8];
-    let bwt_idx = std::str::from_utf8(&index_bytes).unwrap();
-    let bwt_idx_u64: u64 = bwt_idx.parse().unwrap();
+    Ok((meta, obfuscated_bytes.to_vec()))
+}
 
-    //let huff_bytes = &compressed_program_bytes[7..11];
-    //let huf_bitlens = huff_bytes.to_vec();
+// Header and size of every layer, in the order they are applied when writing
+pub struct DpqlzReport {
+    pub meta: DpqlzMeta,
+    pub stage_sizes: Vec<(&'static str, usize)>,
+}
 
-    // Create a DpqlzMeta struct with the extracted metadata
-    let meta = DpqlzMeta {
-        mlen: mlen_u64,
-        moffset: moffset_u8,
-        bwt_idx: bwt_idx_u64,
-        //huf_bitlens,
-    };
+pub fn inspect_diropqlz(program: &str) -> Result<DpqlzReport> {
+
+    let (meta, diropqlz_program) = read_meta(program)?;
+    let (message, sizes) = meta.pipeline.decode_with_sizes(&diropqlz_program, &meta.params)?;
 
-    //println!("MLEN AFTER DECODING: {}", meta.mlen);
-    //println!("MOFFSET AFTER DECODING: {}", meta.moffset);
-    //println!("BWT INDEX AFTER DECODING: {}", meta.bwt_idx);
+    let mut stage_sizes = vec![("message", message.len())];
+    stage_sizes.extend(sizes);
+    stage_sizes.push((meta.pipeline.armor().name(), program.len() - meta.pipeline.armor().magic().len()));
 
-    let obfuscated_bytes = &compressed_program_bytes[8..];
+    Ok(DpqlzReport { meta, stage_sizes })
+}
 
-    (meta, obfuscated_bytes.to_vec())
+// Code tables of a Huffman stage, frequencies counting every byte the stage coded (across all
+// tables when there are several)
+pub struct HuffmanReport {
+    pub stage: usize,
+    pub tables: Vec<Vec<CodeEntry>>,
+}
+
+pub fn inspect_huffman(program: &str) -> Result<Vec<HuffmanReport>> {
+    let (meta, diropqlz_program) = read_meta(program)?;
+    let mut reports = Vec::new();
+    for (i, stage) in meta.pipeline.stages().iter().enumerate() {
+        if stage.id() != HUFFMAN_ID && stage.id() != HUFFMAN_TABLES_ID {
+            continue;
+        }
+        let coded = meta.pipeline.decode_to(&diropqlz_program, &meta.params, i)?;
+        let mut freqs = [0u32; 256];
+        stage.decode(&coded, &meta.params[i])?.iter().for_each(|&byte| freqs[byte as usize] += 1);
+
+        let tables = huffman_code_lengths(stage.id(), &meta.params[i])?
+            .iter()
+            .map(|lengths| {
+                let lengths: Vec<(u8, usize)> = lengths.iter().map(|&(byte, length)| (byte, length as usize)).collect();
+                codebook_entries(&canonical_codebook_from_lengths(&lengths), &freqs)
+            })
+            .collect();
+        reports.push(HuffmanReport { stage: i, tables });
+    }
+    Ok(reports)
 }
@@ -0,0 +1,36 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    MissingMagic,
+    InvalidArmor,
+    InvalidHeader(&'static str),
+    InvalidPayload(&'static str),
+    InvalidProgram(&'static str),
+    InvalidPipeline(String),
+    InvalidBzip2(&'static str),
+    InvalidAlphabet(&'static str),
+    UnknownSymbol(char),
+    InvalidCover(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingMagic => write!(f, "input does not start with a diropqlz magic"),
+            Error::InvalidArmor => write!(f, "input does not match its text armor"),
+            Error::InvalidHeader(reason) => write!(f, "invalid diropqlz header: {}", reason),
+            Error::InvalidPayload(reason) => write!(f, "invalid diropqlz payload: {}", reason),
+            Error::InvalidProgram(reason) => write!(f, "invalid diropql program: {}", reason),
+            Error::InvalidPipeline(reason) => write!(f, "invalid pipeline description: {}", reason),
+            Error::InvalidBzip2(reason) => write!(f, "invalid bzip2 stream: {}", reason),
+            Error::InvalidAlphabet(reason) => write!(f, "invalid alphabet: {}", reason),
+            Error::UnknownSymbol(symbol) => write!(f, "symbol {:?} is not in the alphabet", symbol),
+            Error::InvalidCover(reason) => write!(f, "invalid cover text: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
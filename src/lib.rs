@@ -0,0 +1,9 @@
+pub mod compressor;
+pub mod diropql;
+pub mod error;
+pub mod pipeline;
+
+pub use diropql::stream::{DiropqlzReader, DiropqlzWriter};
+pub use diropql::zip::{read_diropqlz, write_diropqlz};
+pub use error::{Error, Result};
+pub use pipeline::{Pipeline, Stage};
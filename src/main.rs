@@ -1,22 +1,351 @@
-extern crate base85;
-use base85::{decode, encode};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
 
-pub mod diropql;
-pub mod compressor;
+use message_obfuscation::compressor::huffman::symbol_label;
+use message_obfuscation::diropql::zip::{inspect_diropqlz, inspect_huffman, read_diropql_bytes, write_diropql};
+use message_obfuscation::diropql::search::{search_diropqlz, search_diropqlz_program};
+use message_obfuscation::diropql::stego::{hide, reveal, Carrier};
+use message_obfuscation::diropql::stream::{split_blobs, DEFAULT_BLOCK_SIZE};
+use message_obfuscation::pipeline::Armor;
+use message_obfuscation::{DiropqlzReader, DiropqlzWriter, Error, Pipeline};
 
+const USAGE: &str = "\
+Usage: diropqlz <COMMAND> [INPUT] [-o OUTPUT] [-p PIPELINE | --pipeline-file FILE] [-a ARMOR]
+       diropqlz encode [INPUT] --cover FILE [--carrier CARRIER] [...]
+       diropqlz decode [INPUT] --hidden [-o OUTPUT]
+       diropqlz inspect [INPUT] [-o OUTPUT] [--huffman]
+       diropqlz search PATTERN [INPUT] [-o OUTPUT] [--count] [--program]
 
-fn main() {
-    let message = "Kuromi is such a cute character. She is so me! Kuromi is such a cute character. She is so me! Kuromi is such a cute character. She is so me! Kuromi is such a cute character. She is so me! Kuromi is such a cute character.".to_string();
+Commands:
+  encode   Obfuscate text into a diropqlz blob
+  decode   Recover the text hidden in a diropqlz blob
+  compile  Translate text into a diropql program
+  run      Execute a diropql program and print its output
+  inspect  Show the header and per-stage sizes of a diropqlz blob
+  search   Print the offset of every occurrence of PATTERN in the hidden text
 
-    let drpql_program = diropql::zip::write_diropql(&message);
-    println!("Encoded Diropql program: {}", drpql_program);
+INPUT defaults to standard input and OUTPUT to standard output; '-' selects them explicitly.
+encode and decode stream their input, emitting one diropqlz blob per line.
 
-    let read_program = diropql::zip::read_diropql(&drpql_program);
-    println!("Decoded Diropql program: {}", read_program);
+encode takes the stages to run from -p/--pipeline, e.g. 'diropql|bwt:block=4k|mtf|rle|huffman|base85',
+or from a file with one stage per line. Decoding reads the stages from each blob's header.
+-a/--armor picks the text armor of the blobs: base85 (the default), base64, base64url, base32,
+base58, z85, ascii85, hex, base7, which writes only the letters diropql ('base7:wrap=72' breaks
+it into lines), or words, one word per byte and a checksum word, for reading aloud. Decoding
+recognises the armor by the blob's magic.
 
-    let drpqlz_program = diropql::zip::write_diropqlz(&message);
-    println!("Encoded Diropqlz program: {}", drpqlz_program);
+encode --cover hides the blobs in the text of FILE instead of printing them: --carrier zero-width
+(the default) puts invisible characters after its spaces, --carrier whitespace puts spaces and tabs
+at the ends of its lines. decode --hidden reads INPUT as such a cover text.
 
-    let readz_program = diropql::zip::read_diropqlz(&drpqlz_program);
-    println!("Decoded Diropqlz program: {}", readz_program);
+inspect --huffman also prints the code table of every Huffman stage: each byte with how often it
+was coded, and its code.
+
+search looks PATTERN up in the stored BWT blocks instead of decoding the blobs, so the pipeline has
+to start with bwt or diropql|bwt. --count prints the number of matches instead of their offsets and
+--program searches the diropql program rather than the text. Matches spanning two blocks are missed.";
+
+enum Command {
+    Encode,
+    Decode,
+    Compile,
+    Run,
+    Inspect,
+    Search,
+    Help,
+}
+
+struct Args {
+    command: Command,
+    input: Option<String>,
+    output: Option<String>,
+    pipeline: Option<PipelineSource>,
+    armor: Option<Armor>,
+    cover: Option<String>,
+    carrier: Option<Carrier>,
+    hidden: bool,
+    pattern: Option<String>,
+    count: bool,
+    program: bool,
+    huffman: bool,
+}
+
+enum PipelineSource {
+    Spec(String),
+    File(String),
+}
+
+// Usage mistakes exit with 2, everything that goes wrong afterwards with 1
+enum Failure {
+    Usage(String),
+    Runtime(String),
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, Failure> {
+    let command = match args.next().as_deref() {
+        Some("encode") => Command::Encode,
+        Some("decode") => Command::Decode,
+        Some("compile") => Command::Compile,
+        Some("run") => Command::Run,
+        Some("inspect") => Command::Inspect,
+        Some("search") => Command::Search,
+        Some("help" | "-h" | "--help") => Command::Help,
+        Some(other) => return Err(Failure::Usage(format!("unknown command '{}'", other))),
+        None => return Err(Failure::Usage("missing command".to_string())),
+    };
+
+    let mut input = None;
+    let mut output = None;
+    let mut pipeline = None;
+    let mut armor = None;
+    let (mut cover, mut carrier, mut hidden) = (None, None, false);
+    let mut pattern = None;
+    let (mut count, mut program, mut huffman) = (false, false, false);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(path),
+                None => return Err(Failure::Usage(format!("'{}' needs a file name", arg))),
+            },
+            "-p" | "--pipeline" | "--pipeline-file" => {
+                let value = match args.next() {
+                    Some(value) => value,
+                    None => return Err(Failure::Usage(format!("'{}' needs a value", arg))),
+                };
+                if pipeline.is_some() {
+                    return Err(Failure::Usage("the pipeline can only be given once".to_string()));
+                }
+                pipeline = Some(match arg.as_str() {
+                    "--pipeline-file" => PipelineSource::File(value),
+                    _ => PipelineSource::Spec(value),
+                });
+            }
+            "-a" | "--armor" => {
+                let spec = args.next().ok_or_else(|| Failure::Usage(format!("'{}' needs an armor name", arg)))?;
+                armor = Some(spec.parse::<Armor>().map_err(|e| Failure::Usage(e.to_string()))?);
+            }
+            "--cover" if matches!(command, Command::Encode) => {
+                cover = Some(args.next().ok_or_else(|| Failure::Usage(format!("'{}' needs a file name", arg)))?);
+            }
+            "--carrier" if matches!(command, Command::Encode) => {
+                let name = args.next().ok_or_else(|| Failure::Usage(format!("'{}' needs a carrier name", arg)))?;
+                carrier = Some(
+                    Carrier::from_name(&name).ok_or_else(|| Failure::Usage(format!("unknown carrier '{}'", name)))?,
+                );
+            }
+            "--hidden" if matches!(command, Command::Decode) => hidden = true,
+            "--count" | "--program" if matches!(command, Command::Search) => match arg.as_str() {
+                "--count" => count = true,
+                _ => program = true,
+            },
+            "--huffman" if matches!(command, Command::Inspect) => huffman = true,
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(Failure::Usage(format!("unknown option '{}'", arg)))
+            }
+            _ if matches!(command, Command::Search) && pattern.is_none() => pattern = Some(arg),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(Failure::Usage(format!("unexpected argument '{}'", arg))),
+        }
+    }
+
+    if pipeline.is_some() && !matches!(command, Command::Encode) {
+        return Err(Failure::Usage("only encode takes a pipeline".to_string()));
+    }
+    if armor.is_some() && !matches!(command, Command::Encode) {
+        return Err(Failure::Usage("only encode takes an armor".to_string()));
+    }
+
+    if carrier.is_some() && cover.is_none() {
+        return Err(Failure::Usage("'--carrier' needs a cover text from '--cover'".to_string()));
+    }
+
+    if matches!(command, Command::Search) && pattern.as_deref().is_none_or(str::is_empty) {
+        return Err(Failure::Usage("search needs a non-empty PATTERN".to_string()));
+    }
+
+    Ok(Args { command, input, output, pipeline, armor, cover, carrier, hidden, pattern, count, program, huffman })
+}
+
+fn open_input(path: Option<&str>) -> Result<Box<dyn Read>, Failure> {
+    match path {
+        None | Some("-") => Ok(Box::new(io::stdin())),
+        Some(path) => fs::File::open(path)
+            .map(|file| Box::new(file) as Box<dyn Read>)
+            .map_err(|e| Failure::Runtime(format!("cannot read {}: {}", path, e))),
+    }
+}
+
+fn open_output(path: Option<&str>) -> Result<Box<dyn Write>, Failure> {
+    match path {
+        None | Some("-") => Ok(Box::new(io::stdout())),
+        Some(path) => fs::File::create(path)
+            .map(|file| Box::new(file) as Box<dyn Write>)
+            .map_err(|e| Failure::Runtime(format!("cannot write {}: {}", path, e))),
+    }
+}
+
+fn read_input(path: Option<&str>) -> Result<String, Failure> {
+    let mut text = String::new();
+    open_input(path)?
+        .read_to_string(&mut text)
+        .map_err(|e| Failure::Runtime(format!("cannot read {}: {}", path.unwrap_or("standard input"), e)))?;
+    Ok(text)
+}
+
+fn write_output(path: Option<&str>, data: &[u8]) -> Result<(), Failure> {
+    open_output(path)?
+        .write_all(data)
+        .map_err(|e| Failure::Runtime(format!("cannot write {}: {}", path.unwrap_or("standard output"), e)))
+}
+
+fn inspect(blob: &str, huffman: bool) -> Result<String, Error> {
+    let report = inspect_diropqlz(blob)?;
+    let mut out = String::new();
+
+    out.push_str(&format!("mlen:     {}\n", report.meta.mlen));
+    out.push_str(&format!("pipeline: {}\n", report.meta.pipeline));
+    out.push_str("stages:\n");
+    for (stage, size) in &report.stage_sizes {
+        out.push_str(&format!("  {:<8} {} bytes\n", stage, size));
+    }
+
+    let reports = if huffman { inspect_huffman(blob)? } else { Vec::new() };
+    for report in &reports {
+        for (i, table) in report.tables.iter().enumerate() {
+            out.push_str(&format!("huffman codes, stage {} table {} of {}:\n", report.stage + 1, i + 1, report.tables.len()));
+            out.push_str("  symbol      count  length  code\n");
+            for entry in table {
+                out.push_str(&format!(
+                    "  {:<6} {:>10} {:>7}  {}\n",
+                    symbol_label(entry.symbol),
+                    entry.frequency,
+                    entry.code.len(),
+                    entry.code
+                ));
+            }
+        }
+    }
+    Ok(out)
+}
+
+// A stream holds one blob per line, each is reported on its own
+fn inspect_stream(input: &str, huffman: bool) -> Result<Vec<u8>, Error> {
+    let reports = split_blobs(input)
+        .iter()
+        .map(|blob| inspect(blob, huffman))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(reports.join("\n").into_bytes())
+}
+
+// Offsets continue across blobs, as if the stream had been decoded in one piece
+fn search_stream(input: &str, pattern: &[u8], count: bool, program: bool) -> Result<Vec<u8>, Error> {
+    let (mut offsets, mut base) = (Vec::new(), 0);
+    for blob in &split_blobs(input) {
+        let found = if program { search_diropqlz_program(blob, pattern)? } else { search_diropqlz(blob, pattern)? };
+        offsets.extend(found.offsets.iter().map(|offset| base + offset));
+        base += found.len;
+    }
+
+    let out = if count {
+        format!("{}\n", offsets.len())
+    } else {
+        offsets.iter().map(|offset| format!("{}\n", offset)).collect()
+    };
+    Ok(out.into_bytes())
+}
+
+fn load_pipeline(source: Option<&PipelineSource>) -> Result<Pipeline, Failure> {
+    let spec = match source {
+        None => return Ok(Pipeline::default()),
+        Some(PipelineSource::Spec(spec)) => spec.clone(),
+        Some(PipelineSource::File(path)) => read_input(Some(path))?,
+    };
+    spec.parse().map_err(|e: Error| Failure::Usage(e.to_string()))
+}
+
+fn encode(mut input: Box<dyn Read>, output: Box<dyn Write>, pipeline: Pipeline) -> io::Result<()> {
+    let mut writer = DiropqlzWriter::with_pipeline(output, pipeline, DEFAULT_BLOCK_SIZE);
+    io::copy(&mut input, &mut writer)?;
+    writer.finish().map(|_| ())
+}
+
+fn decode(input: Box<dyn Read>, mut output: Box<dyn Write>) -> io::Result<()> {
+    io::copy(&mut DiropqlzReader::new(input), &mut output)?;
+    output.flush()
+}
+
+// The cover has to be read whole, so hiding and revealing do not stream
+fn encode_hidden(
+    mut input: Box<dyn Read>,
+    mut output: Box<dyn Write>,
+    pipeline: Pipeline,
+    cover: &str,
+    carrier: Carrier,
+) -> io::Result<()> {
+    let mut writer = DiropqlzWriter::with_pipeline(Vec::new(), pipeline, DEFAULT_BLOCK_SIZE);
+    io::copy(&mut input, &mut writer)?;
+    let blobs = String::from_utf8_lossy(&writer.finish()?).into_owned();
+    output.write_all(hide(&blobs, cover, carrier).as_bytes())?;
+    output.flush()
+}
+
+fn decode_hidden(mut input: Box<dyn Read>, output: Box<dyn Write>) -> io::Result<()> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let blobs = reveal(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    decode(Box::new(io::Cursor::new(blobs.into_bytes())), output)
+}
+
+fn execute(args: &Args) -> Result<(), Failure> {
+    let output = match args.command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Command::Encode | Command::Decode => {
+            let mut pipeline = load_pipeline(args.pipeline.as_ref())?;
+            if let Some(armor) = args.armor {
+                pipeline = pipeline.with_armor(armor);
+            }
+            let cover = args.cover.as_deref().map(|path| read_input(Some(path))).transpose()?;
+            let input = open_input(args.input.as_deref())?;
+            let output = open_output(args.output.as_deref())?;
+            let result = match (&args.command, cover) {
+                (Command::Encode, Some(cover)) => {
+                    encode_hidden(input, output, pipeline, &cover, args.carrier.unwrap_or_default())
+                }
+                (Command::Encode, None) => encode(input, output, pipeline),
+                _ if args.hidden => decode_hidden(input, output),
+                _ => decode(input, output),
+            };
+            return result.map_err(|e| Failure::Runtime(e.to_string()));
+        }
+        Command::Compile => Ok((write_diropql(&read_input(args.input.as_deref())?) + "\n").into_bytes()),
+        Command::Run => read_diropql_bytes(&read_input(args.input.as_deref())?),
+        Command::Inspect => inspect_stream(&read_input(args.input.as_deref())?, args.huffman),
+        Command::Search => {
+            let pattern = args.pattern.as_deref().unwrap_or_default().as_bytes();
+            search_stream(&read_input(args.input.as_deref())?, pattern, args.count, args.program)
+        }
+    };
+
+    let output = output.map_err(|e| Failure::Runtime(e.to_string()))?;
+    write_output(args.output.as_deref(), &output)
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(|args| execute(&args));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Runtime(message)) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
 }
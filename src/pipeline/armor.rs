@@ -0,0 +1,489 @@
+// Text armor around a whole container. Every blob starts with "DIROPQL" and a letter naming its
+// armor, so read_diropqlz can tell them apart; base85 has the letter Z, which keeps blobs written
+// before there was a choice readable. The magic is letters only, and so are the base32, base58,
+// hex and base7 armors, for channels that reject punctuation; the words armor adds spaces.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+use super::words::WORDS;
+
+pub const MAGIC_PREFIX: &str = "DIROPQL";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Armor {
+    // RFC 1924 base85 as provided by the base85 crate
+    #[default]
+    Base85,
+    // RFC 4648 base64, padded with '='
+    Base64,
+    // RFC 4648 base64 with '-' and '_' for '+' and '/', unpadded
+    Base64Url,
+    // RFC 4648 base32, unpadded since '=' is punctuation too
+    Base32,
+    // Bitcoin's base58, which leaves out 0, O, I and l
+    Base58,
+    // ZeroMQ's Z85, extended to any length by shortening the last group as Ascii85 does
+    Z85,
+    // Adobe's Ascii85 between <~ and ~>, with z for four zero bytes
+    Ascii85,
+    // Lowercase base16
+    Hex,
+    // The seven diropql commands as base 7 digits, so the blob reads like another program; wrap
+    // breaks the letters into lines of that many, 0 keeps them on one line
+    Base7 { wrap: usize },
+    // One word of a built-in list per byte and a CRC-8 word at the end, to be read aloud or pasted
+    // into chats that mangle symbols
+    Words,
+}
+
+static BASE85_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+static BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+static BASE64_URL_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+static BASE32_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+static BASE58_CHARS: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+static Z85_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+static ASCII85_CHARS: &[u8] = b"!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstu";
+static HEX_CHARS: &[u8] = b"0123456789abcdef";
+static BASE7_CHARS: &[u8] = b"diropql";
+
+// Seven bytes fit in 20 base 7 digits, as 7^20 > 2^56, which wastes less than a quarter of a digit
+const BASE7_GROUP: usize = 7;
+const BASE7_GROUP_DIGITS: usize = 20;
+// The byte count comes first, in a fixed number of digits
+const BASE7_LENGTH_DIGITS: usize = 12;
+
+impl Armor {
+    pub const ALL: [Armor; 10] = [
+        Armor::Base85,
+        Armor::Base64,
+        Armor::Base64Url,
+        Armor::Base32,
+        Armor::Base58,
+        Armor::Z85,
+        Armor::Ascii85,
+        Armor::Hex,
+        Armor::Base7 { wrap: 0 },
+        Armor::Words,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Armor::Base85 => "base85",
+            Armor::Base64 => "base64",
+            Armor::Base64Url => "base64url",
+            Armor::Base32 => "base32",
+            Armor::Base58 => "base58",
+            Armor::Z85 => "z85",
+            Armor::Ascii85 => "ascii85",
+            Armor::Hex => "hex",
+            Armor::Base7 { .. } => "base7",
+            Armor::Words => "words",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Armor> {
+        Armor::ALL.into_iter().find(|armor| armor.name() == name)
+    }
+
+    // Letter after MAGIC_PREFIX
+    fn tag(self) -> char {
+        match self {
+            Armor::Base85 => 'Z',
+            Armor::Base64 => 'B',
+            Armor::Base64Url => 'U',
+            Armor::Base32 => 'T',
+            Armor::Base58 => 'F',
+            Armor::Z85 => 'Q',
+            Armor::Ascii85 => 'A',
+            Armor::Hex => 'H',
+            Armor::Base7 { .. } => 'D',
+            Armor::Words => 'W',
+        }
+    }
+
+    // The words armor leaves a space after the magic, so that the first word stands on its own
+    pub fn magic(self) -> String {
+        let separator = if self == Armor::Words { " " } else { "" };
+        format!("{}{}{}", MAGIC_PREFIX, self.tag(), separator)
+    }
+
+    pub fn encode(self, data: &[u8]) -> String {
+        match self {
+            Armor::Base85 => base85::encode(data),
+            Armor::Base64 => {
+                let mut text = encode_bits(data, BASE64_CHARS, 6);
+                let padding = (4 - text.len() % 4) % 4;
+                text.extend(std::iter::repeat_n('=', padding));
+                text
+            }
+            Armor::Base64Url => encode_bits(data, BASE64_URL_CHARS, 6),
+            Armor::Base32 => encode_bits(data, BASE32_CHARS, 5),
+            Armor::Base58 => base58_encode(data),
+            Armor::Z85 => encode_85(data, Z85_CHARS, false),
+            Armor::Ascii85 => format!("<~{}~>", encode_85(data, ASCII85_CHARS, true)),
+            Armor::Hex => encode_bits(data, HEX_CHARS, 4),
+            Armor::Base7 { wrap } => base7_encode(data, wrap),
+            Armor::Words => words_encode(data),
+        }
+    }
+
+    // Whitespace is skipped, as line breaks may be added on the way
+    pub fn decode(self, text: &str) -> Result<Vec<u8>> {
+        // Words are told apart by the whitespace between them
+        if self == Armor::Words {
+            return words_decode(text);
+        }
+        let text: String = text.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        match self {
+            Armor::Base85 => base85_decode(&text),
+            Armor::Base64 | Armor::Base64Url => {
+                decode_bits(text.trim_end_matches('='), if self == Armor::Base64 { BASE64_CHARS } else { BASE64_URL_CHARS }, 6)
+            }
+            // Lowercase is read too, as some channels fold case
+            Armor::Base32 => decode_bits(&text.trim_end_matches('=').to_ascii_uppercase(), BASE32_CHARS, 5),
+            Armor::Base58 => base58_decode(&text),
+            Armor::Z85 => decode_85(&text, Z85_CHARS, false),
+            Armor::Ascii85 => {
+                let inner = text.strip_prefix("<~").and_then(|text| text.strip_suffix("~>")).ok_or(Error::InvalidArmor)?;
+                decode_85(inner, ASCII85_CHARS, true)
+            }
+            Armor::Hex => decode_bits(&text.to_ascii_lowercase(), HEX_CHARS, 4),
+            Armor::Base7 { .. } => base7_decode(&text),
+            Armor::Words => unreachable!(),
+        }
+    }
+
+    // The armor a blob's magic names, and the armored text after the magic
+    pub fn detect(blob: &str) -> Result<(Armor, &str)> {
+        let rest = blob.strip_prefix(MAGIC_PREFIX).ok_or(Error::MissingMagic)?;
+        let tag = rest.chars().next().ok_or(Error::MissingMagic)?;
+        let armor = Armor::ALL.into_iter().find(|armor| armor.tag() == tag).ok_or(Error::MissingMagic)?;
+        Ok((armor, &rest[1..]))
+    }
+}
+
+// Armors are written like pipeline stages, as the name and any options: "base7:wrap=72"
+impl FromStr for Armor {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Armor> {
+        let (name, options) = spec.split_once(':').unwrap_or((spec, ""));
+        let mut armor = Armor::from_name(name.trim())
+            .ok_or_else(|| Error::InvalidPipeline(format!("unknown armor '{}'", name.trim())))?;
+
+        for option in options.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+            let (key, value) = option
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| Error::InvalidPipeline(format!("option '{}' has no value", option)))?;
+            match (&mut armor, key) {
+                (Armor::Base7 { wrap }, "wrap") => {
+                    *wrap = value.parse().map_err(|_| {
+                        Error::InvalidPipeline(format!("'wrap' takes a number of letters, not '{}'", value))
+                    })?
+                }
+                _ => return Err(Error::InvalidPipeline(format!("'{}' has no option '{}'", armor.name(), key))),
+            }
+        }
+        Ok(armor)
+    }
+}
+
+impl fmt::Display for Armor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Armor::Base7 { wrap } if *wrap > 0 => write!(f, "{}:wrap={}", self.name(), wrap),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+// Alphabets of 2^bits characters: the data is read as one long bit string, most significant
+// bit first, and the last character is filled up with zero bits
+fn encode_bits(data: &[u8], alphabet: &[u8], bits: u32) -> String {
+    let mut text = String::with_capacity((data.len() * 8).div_ceil(bits as usize));
+    let (mut buffer, mut held) = (0u32, 0u32);
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        held += 8;
+        while held >= bits {
+            held -= bits;
+            text.push(alphabet[((buffer >> held) & ((1 << bits) - 1)) as usize] as char);
+        }
+    }
+    if held > 0 {
+        text.push(alphabet[((buffer << (bits - held)) & ((1 << bits) - 1)) as usize] as char);
+    }
+    text
+}
+
+// Rejects a last character that leaves a whole character's worth of bits or non-zero filling,
+// which no encoder writes
+fn decode_bits(text: &str, alphabet: &[u8], bits: u32) -> Result<Vec<u8>> {
+    let mut values = [u8::MAX; 256];
+    alphabet.iter().enumerate().for_each(|(value, &c)| values[c as usize] = value as u8);
+
+    let mut data = Vec::with_capacity(text.len() * bits as usize / 8);
+    let (mut buffer, mut held) = (0u32, 0u32);
+    for c in text.bytes() {
+        let value = values[c as usize];
+        if value == u8::MAX {
+            return Err(Error::InvalidArmor);
+        }
+        buffer = (buffer << bits) | value as u32;
+        held += bits;
+        if held >= 8 {
+            held -= 8;
+            data.push((buffer >> held) as u8);
+        }
+    }
+    if held >= bits || buffer & ((1 << held) - 1) != 0 {
+        return Err(Error::InvalidArmor);
+    }
+    Ok(data)
+}
+
+// Groups of four bytes as five base 85 digits, most significant first. A last group of n < 4
+// bytes is padded with zeros and only its first n + 1 digits are kept.
+fn encode_85(data: &[u8], alphabet: &[u8], zero_group: bool) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(4) * 5);
+    for chunk in data.chunks(4) {
+        let mut group = [0u8; 4];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(group);
+        if zero_group && value == 0 && chunk.len() == 4 {
+            text.push('z');
+            continue;
+        }
+
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = alphabet[(value % 85) as usize];
+            value /= 85;
+        }
+        text.extend(digits[..chunk.len() + 1].iter().map(|&c| c as char));
+    }
+    text
+}
+
+// A short last group is padded with the highest digit, which rounds its bytes back up
+fn decode_85(text: &str, alphabet: &[u8], zero_group: bool) -> Result<Vec<u8>> {
+    let mut values = [u8::MAX; 256];
+    alphabet.iter().enumerate().for_each(|(value, &c)| values[c as usize] = value as u8);
+
+    let mut data = Vec::with_capacity(text.len() / 5 * 4 + 4);
+    let mut digits = Vec::with_capacity(5);
+    for c in text.bytes() {
+        if zero_group && c == b'z' && digits.is_empty() {
+            data.extend([0; 4]);
+            continue;
+        }
+        let value = values[c as usize];
+        if value == u8::MAX {
+            return Err(Error::InvalidArmor);
+        }
+        digits.push(value);
+        if digits.len() == 5 {
+            data.extend(group_85(&digits)?);
+            digits.clear();
+        }
+    }
+
+    match digits.len() {
+        0 => {}
+        1 => return Err(Error::InvalidArmor),
+        n => {
+            digits.resize(5, 84);
+            data.extend(&group_85(&digits)?[..n - 1]);
+        }
+    }
+    Ok(data)
+}
+
+fn group_85(digits: &[u8]) -> Result<[u8; 4]> {
+    let value = digits.iter().fold(0u64, |value, &digit| value * 85 + digit as u64);
+    u32::try_from(value).map(u32::to_be_bytes).map_err(|_| Error::InvalidArmor)
+}
+
+// Every leading zero byte becomes a '1'; the rest is converted as one big number, which takes
+// time quadratic in the length, so base58 suits short blobs
+fn base58_encode(data: &[u8]) -> String {
+    let zeros = data.iter().take_while(|&&byte| byte == 0).count();
+
+    // Little-endian limbs of five base 58 digits each
+    const LIMB: u64 = 58u64.pow(5);
+    let mut limbs: Vec<u64> = Vec::with_capacity(data.len() * 138 / 500 + 1);
+    for &byte in &data[zeros..] {
+        let mut carry = byte as u64;
+        for limb in limbs.iter_mut() {
+            let value = (*limb << 8) | carry;
+            *limb = value % LIMB;
+            carry = value / LIMB;
+        }
+        while carry > 0 {
+            limbs.push(carry % LIMB);
+            carry /= LIMB;
+        }
+    }
+
+    let mut digits = Vec::with_capacity(limbs.len() * 5);
+    for &limb in &limbs {
+        let mut limb = limb;
+        for _ in 0..5 {
+            digits.push(BASE58_CHARS[(limb % 58) as usize]);
+            limb /= 58;
+        }
+    }
+    while digits.last() == Some(&BASE58_CHARS[0]) {
+        digits.pop();
+    }
+    digits.extend(std::iter::repeat_n(BASE58_CHARS[0], zeros));
+    digits.iter().rev().map(|&c| c as char).collect()
+}
+
+fn base58_decode(text: &str) -> Result<Vec<u8>> {
+    let mut values = [u8::MAX; 256];
+    BASE58_CHARS.iter().enumerate().for_each(|(value, &c)| values[c as usize] = value as u8);
+    let zeros = text.bytes().take_while(|&c| c == BASE58_CHARS[0]).count();
+
+    // Little-endian limbs of 32 bits
+    let mut limbs: Vec<u64> = Vec::with_capacity(text.len() * 733 / 4000 + 1);
+    for c in text.bytes().skip(zeros) {
+        let value = values[c as usize];
+        if value == u8::MAX {
+            return Err(Error::InvalidArmor);
+        }
+        let mut carry = value as u64;
+        for limb in limbs.iter_mut() {
+            let product = *limb * 58 + carry;
+            *limb = product & 0xffff_ffff;
+            carry = product >> 32;
+        }
+        if carry > 0 {
+            limbs.push(carry);
+        }
+    }
+
+    let mut data: Vec<u8> = limbs.iter().flat_map(|&limb| (limb as u32).to_le_bytes()).collect();
+    while data.last() == Some(&0) {
+        data.pop();
+    }
+    data.extend(std::iter::repeat_n(0, zeros));
+    data.reverse();
+    Ok(data)
+}
+
+// Base 7 digits of value, most significant first
+fn push_base7(text: &mut Vec<u8>, mut value: u64, digits: usize) {
+    let start = text.len();
+    text.resize(start + digits, 0);
+    for digit in text[start..].iter_mut().rev() {
+        *digit = BASE7_CHARS[(value % 7) as usize];
+        value /= 7;
+    }
+}
+
+// Fewest base 7 digits that can hold n bytes
+fn base7_digits(n: usize) -> usize {
+    (0..).find(|&digits| 7u128.pow(digits as u32) >= 1u128 << (8 * n)).unwrap()
+}
+
+fn base7_encode(data: &[u8], wrap: usize) -> String {
+    let mut text = Vec::with_capacity(BASE7_LENGTH_DIGITS + data.len().div_ceil(BASE7_GROUP) * BASE7_GROUP_DIGITS);
+    push_base7(&mut text, data.len() as u64, BASE7_LENGTH_DIGITS);
+    for chunk in data.chunks(BASE7_GROUP) {
+        let value = chunk.iter().fold(0u64, |value, &byte| (value << 8) | byte as u64);
+        push_base7(&mut text, value, base7_digits(chunk.len()));
+    }
+
+    let lines: Vec<&[u8]> = match wrap {
+        0 => vec![&text],
+        _ => text.chunks(wrap).collect(),
+    };
+    lines.iter().map(|line| String::from_utf8_lossy(line)).collect::<Vec<_>>().join("\n")
+}
+
+// The letters have to add up to exactly the byte count in the header
+fn base7_decode(text: &str) -> Result<Vec<u8>> {
+    let mut values = [u8::MAX; 256];
+    BASE7_CHARS.iter().enumerate().for_each(|(value, &c)| values[c as usize] = value as u8);
+    let digits: Vec<u64> = text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| values[c as usize])
+        .map(|value| if value == u8::MAX { Err(Error::InvalidArmor) } else { Ok(value as u64) })
+        .collect::<Result<_>>()?;
+    let number = |digits: &[u64]| digits.iter().try_fold(0u64, |value, &digit| value.checked_mul(7)?.checked_add(digit));
+
+    let (header, body) = digits.split_at_checked(BASE7_LENGTH_DIGITS).ok_or(Error::InvalidArmor)?;
+    let len = number(header).and_then(|len| usize::try_from(len).ok()).ok_or(Error::InvalidArmor)?;
+    let (full, rest) = (len / BASE7_GROUP, len % BASE7_GROUP);
+    let expected = full.checked_mul(BASE7_GROUP_DIGITS).and_then(|digits| digits.checked_add(base7_digits(rest)));
+    if expected != Some(body.len()) {
+        return Err(Error::InvalidArmor);
+    }
+
+    let mut data = Vec::with_capacity(len);
+    let (groups, last) = body.split_at(full * BASE7_GROUP_DIGITS);
+    for (group, n) in groups.chunks(BASE7_GROUP_DIGITS).map(|group| (group, BASE7_GROUP)).chain([(last, rest)]) {
+        let value = number(group).filter(|&value| value >> (8 * n) == 0).ok_or(Error::InvalidArmor)?;
+        data.extend(&value.to_be_bytes()[8 - n..]);
+    }
+    Ok(data)
+}
+
+// CRC-8 with the 0x07 polynomial, which catches any single wrong word and most other slips
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 })
+    })
+}
+
+// The byte count is checked too, or the word for the checksum's own CRC could follow it unnoticed
+fn words_checksum(data: &[u8]) -> u8 {
+    crc8(&[data, &(data.len() as u64).to_le_bytes()].concat())
+}
+
+fn words_encode(data: &[u8]) -> String {
+    data.iter().chain([&words_checksum(data)]).map(|&byte| WORDS[byte as usize]).collect::<Vec<_>>().join(" ")
+}
+
+// Case does not matter, and any whitespace may separate the words
+fn words_decode(text: &str) -> Result<Vec<u8>> {
+    let mut data: Vec<u8> = text
+        .split_whitespace()
+        .map(|word| WORDS.binary_search(&word.to_ascii_lowercase().as_str()).map(|byte| byte as u8))
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| Error::InvalidArmor)?;
+    match data.pop() {
+        Some(checksum) if checksum == words_checksum(&data) => Ok(data),
+        _ => Err(Error::InvalidArmor),
+    }
+}
+
+fn is_base85(text: &str) -> bool {
+    let digits: Option<Vec<u64>> = text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .map(|b| BASE85_CHARS.bytes().position(|c| c == b).map(|d| d as u64))
+        .collect();
+
+    // Every group of five digits has to fit into 32 bits; the crate pads a short last group with 126
+    match digits {
+        Some(digits) => digits
+            .chunks(5)
+            .all(|group| group.iter().chain(std::iter::repeat(&126)).take(5).fold(0, |acc, &d| acc * 85 + d) <= u32::MAX as u64),
+        None => false,
+    }
+}
+
+// The base85 crate panics on input it cannot represent, so check it first
+pub(crate) fn base85_decode(text: &str) -> Result<Vec<u8>> {
+    if !is_base85(text) {
+        return Err(Error::InvalidArmor);
+    }
+    base85::decode(text).ok_or(Error::InvalidArmor)
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+use super::{stage_from_spec, Armor, Pipeline, Stage};
+
+// Containers are armored with base85 unless a description ends with another armor; a trailing
+// base85 names that default armor rather than a base85 stage
+pub const CONTAINER_ARMOR: &str = "base85";
+
+// Parses descriptions like "diropql|bwt:block=256k|mtf|rle|huffman|base85".
+// Newlines separate stages as well and '#' starts a comment, so a description
+// can live in a config file with one stage per line.
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    fn from_str(description: &str) -> Result<Pipeline> {
+        let mut elements: Vec<&str> = description
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(|line| line.split('|'))
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+            .collect();
+
+        if elements.is_empty() {
+            return Err(Error::InvalidPipeline("no stages".to_string()));
+        }
+        let is_armor = |element: &str| Armor::from_name(element.split(':').next().unwrap_or_default().trim()).is_some();
+        let armor = match elements.last() {
+            Some(&last) if is_armor(last) => {
+                elements.pop();
+                Some(last.parse::<Armor>()?)
+            }
+            _ => None,
+        };
+        // base85 is a stage as well, the other armors can only wrap the whole container
+        if let Some(&element) = elements.iter().find(|&&element| element != CONTAINER_ARMOR && is_armor(element)) {
+            return Err(Error::InvalidPipeline(format!("the armor '{}' has to be the last element", element)));
+        }
+
+        let stages = elements.into_iter().map(parse_stage).collect::<Result<Vec<_>>>()?;
+        let pipeline = Pipeline { stages, armor: armor.unwrap_or_default() };
+        pipeline.validate()?;
+        Ok(pipeline)
+    }
+}
+
+fn parse_stage(element: &str) -> Result<Box<dyn Stage>> {
+    let (name, options) = element.split_once(':').unwrap_or((element, ""));
+
+    let options = options
+        .split(',')
+        .map(str::trim)
+        .filter(|option| !option.is_empty())
+        .map(|option| {
+            option
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| Error::InvalidPipeline(format!("option '{}' has no value", option)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    stage_from_spec(name.trim(), &options)
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stage in &self.stages {
+            write!(f, "{}", stage.name())?;
+
+            let options: Vec<String> = stage.options().into_iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            if !options.is_empty() {
+                write!(f, ":{}", options.join(","))?;
+            }
+            write!(f, "|")?;
+        }
+        write!(f, "{}", self.armor)
+    }
+}
+
+// Sizes take an optional k or m suffix for KiB and MiB
+pub fn parse_size(value: &str) -> Option<usize> {
+    let (digits, multiplier) = match value.to_ascii_lowercase().chars().last()? {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+pub fn format_size(size: usize) -> String {
+    match size {
+        0 => "0".to_string(),
+        _ if size.is_multiple_of(1024 * 1024) => format!("{}m", size / (1024 * 1024)),
+        _ if size.is_multiple_of(1024) => format!("{}k", size / 1024),
+        _ => size.to_string(),
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+// Byte strings are written as-is, except for characters that mean something in a
+// description, which are written as \xNN
+pub fn parse_bytes(value: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'\\' {
+            let hex = std::str::from_utf8(tail.get(..3)?).ok()?.strip_prefix('x')?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[3..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Some(bytes)
+}
+
+pub fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            b'|' | b':' | b',' | b'=' | b'#' | b'\\' => format!("\\x{:02x}", byte),
+            _ if byte.is_ascii_graphic() => (byte as char).to_string(),
+            _ => format!("\\x{:02x}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,251 @@
+mod armor;
+mod dsl;
+mod stages;
+mod words;
+
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+pub use armor::{Armor, MAGIC_PREFIX};
+pub use dsl::CONTAINER_ARMOR;
+pub use stages::{
+    stage_from_header, stage_from_spec, AdaptiveHuffman, Base85, Bwt, CommandRuns, Diropql, Huffman, Mtf, MtfAlphabet,
+    PackBits, Range, Rans, Rle, Rle1,
+};
+pub(crate) use stages::{bwt_blocks, huffman_code_lengths, BWT_ID, DIROPQL_ID, HUFFMAN_ID, HUFFMAN_TABLES_ID};
+
+const HEADER_VERSION: u8 = 1;
+
+// Output size of every stage, in the order the stages run when encoding
+pub type StageSizes = Vec<(&'static str, usize)>;
+
+// Role of a stage, which decides where it may appear in a pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageKind {
+    // Turns the message into another language, only valid as the first stage
+    Text,
+    // Reorders or recodes symbols to expose redundancy
+    Transform,
+    // Removes redundancy, leaving nothing for later transforms to work with
+    Entropy,
+    // Maps bytes onto printable characters
+    Armor,
+}
+
+// One reversible transformation of a byte string
+pub trait Stage {
+    // Name shown in reports and pipeline descriptions
+    fn name(&self) -> &'static str;
+
+    // Identifier recorded in the container header
+    fn id(&self) -> u8;
+
+    fn kind(&self) -> StageKind;
+
+    // Options in the form accepted by the pipeline description parser
+    fn options(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    // Returns the output and the parameters decode needs, which are recorded in the header
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>>;
+}
+
+// Stages applied in order when encoding and in reverse when decoding, and the text armor
+// wrapped around the container they produce
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+    armor: Armor,
+}
+
+pub struct PipelineBuilder {
+    stages: Vec<Box<dyn Stage>>,
+    armor: Armor,
+}
+
+impl PipelineBuilder {
+    pub fn stage<S: Stage + 'static>(mut self, stage: S) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn armor(mut self, armor: Armor) -> Self {
+        self.armor = armor;
+        self
+    }
+
+    pub fn build(self) -> Pipeline {
+        Pipeline { stages: self.stages, armor: self.armor }
+    }
+}
+
+impl Pipeline {
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder { stages: Vec::new(), armor: Armor::default() }
+    }
+
+    pub fn stages(&self) -> &[Box<dyn Stage>] {
+        &self.stages
+    }
+
+    pub fn armor(&self) -> Armor {
+        self.armor
+    }
+
+    pub fn with_armor(mut self, armor: Armor) -> Self {
+        self.armor = armor;
+        self
+    }
+
+    // Reject orders that cannot work or make no sense, like Huffman coding before the BWT
+    pub fn validate(&self) -> Result<()> {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if stage.kind() == StageKind::Text && i > 0 {
+                return Err(Error::InvalidPipeline(format!("'{}' has to be the first stage", stage.name())));
+            }
+
+            let entropy_coder = self.stages[..i].iter().find(|earlier| earlier.kind() == StageKind::Entropy);
+            if let Some(entropy_coder) = entropy_coder {
+                if stage.kind() != StageKind::Armor {
+                    return Err(Error::InvalidPipeline(format!(
+                        "'{}' cannot follow the entropy coder '{}'",
+                        stage.name(),
+                        entropy_coder.name()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the output and the parameters of every stage
+    pub fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>)> {
+        let mut params = Vec::with_capacity(self.stages.len());
+        let mut data = data.to_vec();
+
+        for stage in &self.stages {
+            let (output, stage_params) = stage.encode(&data)?;
+            data = output;
+            params.push(stage_params);
+        }
+        Ok((data, params))
+    }
+
+    pub fn decode(&self, data: &[u8], params: &[Vec<u8>]) -> Result<Vec<u8>> {
+        self.decode_with_sizes(data, params).map(|(data, _)| data)
+    }
+
+    // Decode while recording the size each stage produced when the data was encoded
+    pub fn decode_with_sizes(&self, data: &[u8], params: &[Vec<u8>]) -> Result<(Vec<u8>, StageSizes)> {
+        if params.len() != self.stages.len() {
+            return Err(Error::InvalidHeader("parameter count does not match the stages"));
+        }
+
+        let mut sizes = Vec::with_capacity(self.stages.len());
+        let mut data = data.to_vec();
+
+        for (stage, stage_params) in self.stages.iter().zip(params).rev() {
+            sizes.push((stage.name(), data.len()));
+            data = stage.decode(&data, stage_params)?;
+        }
+        sizes.reverse();
+        Ok((data, sizes))
+    }
+
+    // Undo only the stages after stages()[stage], giving back what that stage produced
+    pub fn decode_to(&self, data: &[u8], params: &[Vec<u8>], stage: usize) -> Result<Vec<u8>> {
+        if params.len() != self.stages.len() {
+            return Err(Error::InvalidHeader("parameter count does not match the stages"));
+        }
+
+        let mut data = data.to_vec();
+        for (stage, stage_params) in self.stages.iter().zip(params).skip(stage + 1).rev() {
+            data = stage.decode(&data, stage_params)?;
+        }
+        Ok(data)
+    }
+
+    pub fn write_header(&self, params: &[Vec<u8>], output: &mut Vec<u8>) {
+        output.push(HEADER_VERSION);
+        output.push(self.stages.len() as u8);
+
+        for (stage, stage_params) in self.stages.iter().zip(params) {
+            output.push(stage.id());
+            write_varint(output, stage_params.len() as u64);
+            output.extend(stage_params);
+        }
+    }
+
+    // Rebuild the pipeline and the stage parameters, advancing pos past the header. The armor is
+    // not part of the header; it is named by the magic in front of it.
+    pub fn read_header(data: &[u8], pos: &mut usize) -> Result<(Pipeline, Vec<Vec<u8>>)> {
+        let version = *data.get(*pos).ok_or(Error::InvalidHeader("missing version"))?;
+        if version != HEADER_VERSION {
+            return Err(Error::InvalidHeader("unsupported version"));
+        }
+        let count = *data.get(*pos + 1).ok_or(Error::InvalidHeader("missing stage count"))?;
+        *pos += 2;
+
+        let mut stages = Vec::with_capacity(count as usize);
+        let mut params = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let id = *data.get(*pos).ok_or(Error::InvalidHeader("truncated stage list"))?;
+            *pos += 1;
+            let len = read_varint(data, pos).ok_or(Error::InvalidHeader("truncated stage list"))? as usize;
+            let stage_params = data
+                .get(*pos..pos.saturating_add(len))
+                .ok_or(Error::InvalidHeader("truncated stage parameters"))?;
+            *pos += len;
+
+            stages.push(stage_from_header(id, stage_params)?);
+            params.push(stage_params.to_vec());
+        }
+
+        Ok((Pipeline { stages, armor: Armor::default() }, params))
+    }
+}
+
+// diropql -> BWT -> MTF -> RLE -> Huffman, then base85 armor
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::builder()
+            .stage(Diropql)
+            .stage(Bwt::default())
+            .stage(Mtf::default())
+            .stage(Rle)
+            .stage(Huffman::default())
+            .build()
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.stages.iter().map(|stage| stage.name())).finish()
+    }
+}
+
+// LEB128, used for every length and parameter in the header and stage outputs
+pub fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+pub fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
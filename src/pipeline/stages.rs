@@ -0,0 +1,1007 @@
+use crate::compressor::bwt::{bwt_decode_bytes, bwt_encode_bytes, bwts_decode, bwts_encode};
+use crate::compressor::adaptive_huffman::{adaptive_huffman_decode, adaptive_huffman_encode};
+use crate::compressor::bits::{BitReader, BitWriter};
+use crate::compressor::huffman::{
+    choose_tables, huffman_encode, huffman_encode_groups, limited_code_lengths, table_count, HuffmanDecoder, GROUP_SIZE,
+    MAX_CODE_LEN, MAX_TABLES,
+};
+use crate::compressor::mtf::{
+    byte_alphabet, input_alphabet, inversion_frequencies_decode, inversion_frequencies_encode, rank_decode, rank_encode,
+    MtfVariant, MAX_FREE_FILL,
+};
+use crate::compressor::range_coder::{range_decode, range_encode, MAX_ORDER};
+use crate::compressor::rans::{normalize_frequencies, rans_decode, rans_encode, INTERLEAVE, MAX_FREE_COUNT, SCALE_BITS};
+use crate::compressor::rle::{
+    command_runs_decode, command_runs_encode, packbits_decode, packbits_encode, rle1_decode, rle1_encode, rle_decode,
+    rle_encode, MAX_DECODED_LEN,
+};
+use crate::diropql::zip::{read_diropql_bytes, write_diropql_bytes};
+use crate::error::{Error, Result};
+
+use super::armor::base85_decode;
+use super::dsl::{format_bytes, format_size, parse_bool, parse_bytes, parse_size};
+use super::{read_varint, write_varint, Stage, StageKind};
+
+pub(crate) const DIROPQL_ID: u8 = 1;
+pub(crate) const BWT_ID: u8 = 2;
+pub(crate) const MTF_ID: u8 = 3;
+pub(crate) const RLE_ID: u8 = 4;
+pub(crate) const HUFFMAN_ID: u8 = 5;
+pub(crate) const BASE85_ID: u8 = 6;
+pub(crate) const BWTS_ID: u8 = 7;
+pub(crate) const RANK_ID: u8 = 8;
+pub(crate) const PACKBITS_ID: u8 = 9;
+pub(crate) const RLE1_ID: u8 = 10;
+pub(crate) const COMMAND_RUNS_ID: u8 = 11;
+pub(crate) const HUFFMAN_TABLES_ID: u8 = 12;
+pub(crate) const ADAPTIVE_HUFFMAN_ID: u8 = 13;
+pub(crate) const RANGE_ID: u8 = 14;
+pub(crate) const RANS_ID: u8 = 15;
+
+// Rebuild a stage from the id and parameters recorded in a container header
+pub fn stage_from_header(id: u8, params: &[u8]) -> Result<Box<dyn Stage>> {
+    let stage: Box<dyn Stage> = match id {
+        DIROPQL_ID => Box::new(Diropql),
+        BWT_ID => Box::new(Bwt { block_size: read_block_size(params, &mut 0)?, bijective: false }),
+        BWTS_ID => Box::new(Bwt { block_size: read_block_size(params, &mut 0)?, bijective: true }),
+        MTF_ID => Box::new(Mtf::from_params(MtfVariant::MoveToFront, params)),
+        RANK_ID => {
+            let (&variant, alphabet) = params.split_first().ok_or(Error::InvalidHeader("missing MTF variant"))?;
+            let variant = *MtfVariant::ALL.get(variant as usize).ok_or(Error::InvalidHeader("unknown MTF variant"))?;
+            Box::new(Mtf::from_params(variant, alphabet))
+        }
+        RLE_ID => Box::new(Rle),
+        PACKBITS_ID => Box::new(PackBits),
+        RLE1_ID => Box::new(Rle1),
+        COMMAND_RUNS_ID => Box::new(CommandRuns),
+        HUFFMAN_ID => Box::new(Huffman { tables: 1 }),
+        HUFFMAN_TABLES_ID => Box::new(Huffman::default()),
+        ADAPTIVE_HUFFMAN_ID => Box::new(AdaptiveHuffman),
+        RANGE_ID => Box::new(Range { order: read_order(params)? }),
+        RANS_ID => Box::new(Rans { interleaved: read_ways(params)? == INTERLEAVE }),
+        BASE85_ID => Box::new(Base85),
+        _ => return Err(Error::InvalidHeader("unknown stage")),
+    };
+    Ok(stage)
+}
+
+// Non-empty and without repeated symbols
+fn is_alphabet(alphabet: &[u8]) -> bool {
+    let mut seen = [false; 256];
+    !alphabet.is_empty() && alphabet.iter().all(|&b| !std::mem::replace(&mut seen[b as usize], true))
+}
+
+fn unknown_option(name: &str, key: &str) -> Error {
+    Error::InvalidPipeline(format!("'{}' has no option '{}'", name, key))
+}
+
+fn no_options(name: &str, options: &[(&str, &str)]) -> Result<()> {
+    match options.first() {
+        Some(&(key, _)) => Err(unknown_option(name, key)),
+        None => Ok(()),
+    }
+}
+
+// Build a stage from its name and options in a pipeline description
+pub fn stage_from_spec(name: &str, options: &[(&str, &str)]) -> Result<Box<dyn Stage>> {
+    let stage: Box<dyn Stage> = match name {
+        "bwt" => {
+            let mut bwt = Bwt::default();
+            for &(key, value) in options {
+                match key {
+                    "block" => bwt.block_size = parse_size(value).filter(|&size| size > 0).ok_or_else(|| {
+                        Error::InvalidPipeline(format!("invalid BWT block size '{}'", value))
+                    })?,
+                    "bijective" => bwt.bijective = parse_bool(value).ok_or_else(|| {
+                        Error::InvalidPipeline(format!("'bijective' takes true or false, not '{}'", value))
+                    })?,
+                    _ => return Err(unknown_option(name, key)),
+                }
+            }
+            Box::new(bwt)
+        }
+        "mtf" => {
+            let mut mtf = Mtf::default();
+            for &(key, value) in options {
+                match key {
+                    "variant" => mtf.variant = MtfVariant::from_name(value).ok_or_else(|| {
+                        Error::InvalidPipeline(format!("unknown MTF variant '{}'", value))
+                    })?,
+                    "alphabet" => mtf.alphabet = match value {
+                        "all" => MtfAlphabet::Bytes,
+                        "auto" => MtfAlphabet::Input,
+                        _ => parse_bytes(value).filter(|alphabet| is_alphabet(alphabet)).map(MtfAlphabet::Fixed).ok_or_else(|| {
+                            Error::InvalidPipeline(format!("invalid MTF alphabet '{}'", value))
+                        })?,
+                    },
+                    _ => return Err(unknown_option(name, key)),
+                }
+            }
+            if mtf.variant == MtfVariant::InversionFrequencies && options.iter().any(|&(key, _)| key == "alphabet") {
+                return Err(Error::InvalidPipeline("the 'if' MTF variant takes no alphabet".to_string()));
+            }
+            Box::new(mtf)
+        }
+        "diropql" => {
+            no_options(name, options)?;
+            Box::new(Diropql)
+        }
+        "rle" => {
+            no_options(name, options)?;
+            Box::new(Rle)
+        }
+        "packbits" => {
+            no_options(name, options)?;
+            Box::new(PackBits)
+        }
+        "rle1" => {
+            no_options(name, options)?;
+            Box::new(Rle1)
+        }
+        "cmdrle" => {
+            no_options(name, options)?;
+            Box::new(CommandRuns)
+        }
+        "huffman" => {
+            let mut huffman = Huffman::default();
+            for &(key, value) in options {
+                match key {
+                    "tables" => huffman.tables = match value {
+                        "auto" => 0,
+                        _ => value.parse().ok().filter(|tables| (1..=MAX_TABLES).contains(tables)).ok_or_else(|| {
+                            Error::InvalidPipeline(format!("'tables' takes auto or 1 to {}, not '{}'", MAX_TABLES, value))
+                        })?,
+                    },
+                    _ => return Err(unknown_option(name, key)),
+                }
+            }
+            Box::new(huffman)
+        }
+        "ahuffman" => {
+            no_options(name, options)?;
+            Box::new(AdaptiveHuffman)
+        }
+        "range" => {
+            let mut range = Range::default();
+            for &(key, value) in options {
+                match key {
+                    "order" => range.order = value.parse().ok().filter(|&order| order <= MAX_ORDER).ok_or_else(|| {
+                        Error::InvalidPipeline(format!("'order' takes 0 to {}, not '{}'", MAX_ORDER, value))
+                    })?,
+                    _ => return Err(unknown_option(name, key)),
+                }
+            }
+            Box::new(range)
+        }
+        "rans" => {
+            let mut rans = Rans::default();
+            for &(key, value) in options {
+                match key {
+                    "interleave" => rans.interleaved = parse_bool(value).ok_or_else(|| {
+                        Error::InvalidPipeline(format!("'interleave' takes true or false, not '{}'", value))
+                    })?,
+                    _ => return Err(unknown_option(name, key)),
+                }
+            }
+            Box::new(rans)
+        }
+        "base85" => {
+            no_options(name, options)?;
+            Box::new(Base85)
+        }
+        _ => return Err(Error::InvalidPipeline(format!("unknown stage '{}'", name))),
+    };
+    Ok(stage)
+}
+
+// Text encoder: every byte becomes a run of increments followed by an output command, after
+// a loop clearing the cell when the program has gone round all of memory
+pub struct Diropql;
+
+impl Stage for Diropql {
+    fn name(&self) -> &'static str {
+        "diropql"
+    }
+
+    fn id(&self) -> u8 {
+        DIROPQL_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Text
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        Ok((write_diropql_bytes(data).into_bytes(), Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8], _params: &[u8]) -> Result<Vec<u8>> {
+        let program = std::str::from_utf8(data).map_err(|_| Error::InvalidProgram("not ASCII"))?;
+        read_diropql_bytes(program)
+    }
+}
+
+// Burrows-Wheeler transform over fixed-size blocks; the parameters hold the block size
+// followed by the primary index of every block. The bijective variant needs no sentinel and
+// no indices, so its parameters are the block size alone and it has an id of its own.
+pub struct Bwt {
+    pub block_size: usize,
+    pub bijective: bool,
+}
+
+impl Default for Bwt {
+    fn default() -> Self {
+        Bwt { block_size: 256 * 1024, bijective: false }
+    }
+}
+
+fn read_block_size(params: &[u8], pos: &mut usize) -> Result<usize> {
+    read_varint(params, pos)
+        .filter(|&size| size > 0 && size < usize::MAX as u64)
+        .map(|size| size as usize)
+        .ok_or(Error::InvalidHeader("malformed BWT block size"))
+}
+
+impl Stage for Bwt {
+    fn name(&self) -> &'static str {
+        "bwt"
+    }
+
+    fn id(&self) -> u8 {
+        if self.bijective {
+            BWTS_ID
+        } else {
+            BWT_ID
+        }
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Transform
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![("block", format_size(self.block_size))];
+        if self.bijective {
+            options.push(("bijective", "true".to_string()));
+        }
+        options
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut output = Vec::with_capacity(data.len() + data.len() / self.block_size + 1);
+        let mut params = Vec::new();
+        write_varint(&mut params, self.block_size as u64);
+
+        if self.bijective {
+            data.chunks(self.block_size).for_each(|block| output.extend(bwts_encode(block)));
+            return Ok((output, params));
+        }
+        for block in data.chunks(self.block_size) {
+            let (transformed, index) = bwt_encode_bytes(block);
+            write_varint(&mut params, index as u64);
+            output.extend(transformed);
+        }
+        Ok((output, params))
+    }
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+        let mut pos = 0;
+        let block_size = read_block_size(params, &mut pos)?;
+        let mut output = Vec::with_capacity(data.len());
+
+        if self.bijective {
+            data.chunks(block_size).for_each(|block| output.extend(bwts_decode(block)));
+            return Ok(output);
+        }
+
+        for (block, index) in bwt_blocks(data, params)? {
+            let decoded = bwt_decode_bytes(block, index);
+            output.extend_from_slice(&decoded[..decoded.len() - 1]);
+        }
+        Ok(output)
+    }
+}
+
+// Split the output of the classic BWT stage into its blocks and their primary indices.
+// Every block carries one sentinel on top of its data.
+pub(crate) fn bwt_blocks<'a>(data: &'a [u8], params: &[u8]) -> Result<Vec<(&'a [u8], usize)>> {
+    let mut pos = 0;
+    let block_size = read_block_size(params, &mut pos)?;
+
+    let blocks = data
+        .chunks(block_size.saturating_add(1))
+        .map(|block| {
+            let index = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing BWT index"))? as usize;
+            if index >= block.len() {
+                return Err(Error::InvalidHeader("BWT index out of range"));
+            }
+            Ok((block, index))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // One index per block and nothing more, or the header has been tampered with
+    if pos != params.len() {
+        return Err(Error::InvalidHeader("more BWT indices than blocks"));
+    }
+    Ok(blocks)
+}
+
+// Move-to-front, by default over the diropql commands plus the BWT sentinel;
+// the alphabet is the parameter. The other ranking variants have an id of their own
+// and record the variant in front of the alphabet.
+pub struct Mtf {
+    pub alphabet: MtfAlphabet,
+    pub variant: MtfVariant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MtfAlphabet {
+    // Given up front and recorded as the parameters
+    Fixed(Vec<u8>),
+    // All 256 byte values, recorded as empty parameters
+    Bytes,
+    // The bytes the input uses, worked out per encode and recorded like a fixed alphabet
+    Input,
+}
+
+impl Default for Mtf {
+    fn default() -> Self {
+        Mtf {
+            alphabet: MtfAlphabet::Fixed(b"\0diropql".to_vec()),
+            variant: MtfVariant::MoveToFront,
+        }
+    }
+}
+
+impl Mtf {
+    fn from_params(variant: MtfVariant, alphabet: &[u8]) -> Self {
+        let alphabet = match alphabet {
+            [] => MtfAlphabet::Bytes,
+            _ => MtfAlphabet::Fixed(alphabet.to_vec()),
+        };
+        Mtf { alphabet, variant }
+    }
+
+    // The alphabet part of the parameters
+    fn alphabet_params<'a>(&self, params: &'a [u8]) -> &'a [u8] {
+        match self.variant {
+            MtfVariant::MoveToFront => params,
+            _ => params.get(1..).unwrap_or_default(),
+        }
+    }
+}
+
+impl Stage for Mtf {
+    fn name(&self) -> &'static str {
+        "mtf"
+    }
+
+    fn id(&self) -> u8 {
+        match self.variant {
+            MtfVariant::MoveToFront => MTF_ID,
+            _ => RANK_ID,
+        }
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Transform
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        let mut options = Vec::new();
+        if self.variant != MtfVariant::MoveToFront {
+            options.push(("variant", self.variant.name().to_string()));
+        }
+
+        let alphabet = match &self.alphabet {
+            _ if self.variant == MtfVariant::InversionFrequencies => None,
+            alphabet if *alphabet == Mtf::default().alphabet => None,
+            MtfAlphabet::Fixed(alphabet) => Some(format_bytes(alphabet)),
+            MtfAlphabet::Bytes => Some("all".to_string()),
+            MtfAlphabet::Input => Some("auto".to_string()),
+        };
+        options.extend(alphabet.map(|alphabet| ("alphabet", alphabet)));
+        options
+    }
+
+    // Symbols outside a fixed alphabet are an error rather than silently mis-encoded
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut params = Vec::new();
+        if self.variant != MtfVariant::MoveToFront {
+            params.push(MtfVariant::ALL.iter().position(|&variant| variant == self.variant).unwrap() as u8);
+        }
+        if self.variant == MtfVariant::InversionFrequencies {
+            let largest = data.iter().max().map_or(0, |&largest| data.iter().filter(|&&byte| byte == largest).count());
+            if largest > MAX_FREE_FILL {
+                return Err(Error::InvalidPayload("too many of the largest byte for inversion frequencies"));
+            }
+            return Ok((inversion_frequencies_encode(data), params));
+        }
+
+        let alphabet = match &self.alphabet {
+            MtfAlphabet::Fixed(alphabet) => alphabet.clone(),
+            MtfAlphabet::Bytes => byte_alphabet(),
+            MtfAlphabet::Input => input_alphabet(data),
+        };
+        if self.alphabet != MtfAlphabet::Bytes {
+            params.extend(&alphabet);
+        }
+        Ok((rank_encode(data, &alphabet, self.variant)?, params))
+    }
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+        if self.variant == MtfVariant::InversionFrequencies {
+            return inversion_frequencies_decode(data);
+        }
+        let alphabet = match self.alphabet_params(params) {
+            [] => byte_alphabet(),
+            alphabet => alphabet.to_vec(),
+        };
+        rank_decode(data, &alphabet, self.variant)
+    }
+}
+
+// Zero-run coder for move-to-front output; its u16 symbols are stored as varints, so the
+// common values below 126 still take a single byte
+pub struct Rle;
+
+impl Stage for Rle {
+    fn name(&self) -> &'static str {
+        "rle"
+    }
+
+    fn id(&self) -> u8 {
+        RLE_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Transform
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        if data.len() > MAX_DECODED_LEN {
+            return Err(Error::InvalidPayload("input too long for RLE"));
+        }
+        let mut out = Vec::with_capacity(data.len());
+        for symbol in rle_encode(data) {
+            write_varint(&mut out, symbol as u64);
+        }
+        Ok((out, Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8], _params: &[u8]) -> Result<Vec<u8>> {
+        let mut symbols = Vec::with_capacity(data.len());
+        let mut pos = 0;
+        while pos < data.len() {
+            let symbol = read_varint(data, &mut pos).ok_or(Error::InvalidPayload("truncated RLE symbol"))?;
+            symbols.push(u16::try_from(symbol).map_err(|_| Error::InvalidPayload("invalid RLE symbol"))?);
+        }
+        rle_decode(&symbols)
+    }
+}
+
+// PackBits literal and run packets
+pub struct PackBits;
+
+impl Stage for PackBits {
+    fn name(&self) -> &'static str {
+        "packbits"
+    }
+
+    fn id(&self) -> u8 {
+        PACKBITS_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Transform
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        Ok((packbits_encode(data), Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8], _params: &[u8]) -> Result<Vec<u8>> {
+        packbits_decode(data)
+    }
+}
+
+// bzip2's initial run-length encoding, four equal bytes and a repeat count
+pub struct Rle1;
+
+impl Stage for Rle1 {
+    fn name(&self) -> &'static str {
+        "rle1"
+    }
+
+    fn id(&self) -> u8 {
+        RLE1_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Transform
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        Ok((rle1_encode(data), Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8], _params: &[u8]) -> Result<Vec<u8>> {
+        Ok(rle1_decode(data))
+    }
+}
+
+// Command runs of a diropql program as count tokens, meant to go before the BWT. The tokens
+// are not in the default MTF alphabet, so a later mtf stage needs alphabet=auto or all.
+pub struct CommandRuns;
+
+impl Stage for CommandRuns {
+    fn name(&self) -> &'static str {
+        "cmdrle"
+    }
+
+    fn id(&self) -> u8 {
+        COMMAND_RUNS_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Transform
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        Ok((command_runs_encode(data), Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8], _params: &[u8]) -> Result<Vec<u8>> {
+        command_runs_decode(data)
+    }
+}
+
+// Canonical Huffman coding. With one table the parameters hold the symbol count and a
+// (symbol, length) pair for every symbol used. With several, as in bzip2, every group of 50
+// symbols picks its table: the parameters hold the symbol count, the symbols used, the table
+// count, four-bit code lengths for every table and the move-to-front coded selectors in unary.
+// tables is 0 to try every count up to bzip2's for the input size and keep the smallest.
+#[derive(Default)]
+pub struct Huffman {
+    pub tables: usize,
+}
+
+impl Stage for Huffman {
+    fn name(&self) -> &'static str {
+        "huffman"
+    }
+
+    fn id(&self) -> u8 {
+        if self.tables == 1 {
+            HUFFMAN_ID
+        } else {
+            HUFFMAN_TABLES_ID
+        }
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Entropy
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        match self.tables {
+            0 => Vec::new(),
+            tables => vec![("tables", tables.to_string())],
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        if self.tables == 1 {
+            huffman_single_encode(data)
+        } else {
+            huffman_tables_encode(data, self.tables)
+        }
+    }
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+        if self.tables == 1 {
+            huffman_single_decode(data, params)
+        } else {
+            huffman_tables_decode(data, params)
+        }
+    }
+}
+
+fn huffman_single_encode(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut params = Vec::new();
+    write_varint(&mut params, data.len() as u64);
+    if data.is_empty() {
+        return Ok((Vec::new(), params));
+    }
+
+    let mut freqs = [0u32; 256];
+    data.iter().for_each(|&byte| freqs[byte as usize] += 1);
+
+    // Codes are at most MAX_CODE_LEN bits long, which is always enough for 256 symbols
+    let lengths = limited_code_lengths(&freqs, MAX_CODE_LEN).unwrap();
+
+    write_varint(&mut params, lengths.iter().filter(|&&length| length > 0).count() as u64);
+    for (value, &length) in lengths.iter().enumerate().filter(|&(_, &length)| length > 0) {
+        params.push(value as u8);
+        params.push(length);
+    }
+    Ok((huffman_encode(data, &lengths), params))
+}
+
+fn huffman_single_decode(data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let count = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing Huffman symbol count"))? as usize;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let lengths = read_single_lengths(params, &mut pos)?;
+
+    // The padding bits of the last byte are never read
+    let decoded = HuffmanDecoder::new(&lengths)?.decode(data, count)?;
+    Ok(decoded.into_iter().map(|symbol| symbol as u8).collect())
+}
+
+// The (byte, code length) pairs of the single-table format, as lengths indexed by byte
+fn read_single_lengths(params: &[u8], pos: &mut usize) -> Result<[u8; 256]> {
+    let symbols = read_varint(params, pos).ok_or(Error::InvalidHeader("missing Huffman code lengths"))? as usize;
+    let table = params
+        .get(*pos..pos.saturating_add(symbols.saturating_mul(2)))
+        .ok_or(Error::InvalidHeader("truncated Huffman code lengths"))?;
+    *pos += table.len();
+
+    let mut lengths = [0u8; 256];
+    for pair in table.chunks(2) {
+        if pair[1] == 0 || lengths[pair[0] as usize] != 0 {
+            return Err(Error::InvalidHeader("invalid Huffman code length"));
+        }
+        lengths[pair[0] as usize] = pair[1];
+    }
+    Ok(lengths)
+}
+
+fn huffman_tables_encode(data: &[u8], tables: usize) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut params = Vec::new();
+    write_varint(&mut params, data.len() as u64);
+    if data.is_empty() {
+        return Ok((Vec::new(), params));
+    }
+
+    // Code the bytes in use as 0, 1, 2... so the tables only cover those
+    let mut in_use = [false; 256];
+    data.iter().for_each(|&byte| in_use[byte as usize] = true);
+    let used: Vec<u8> = (0..=255).filter(|&byte| in_use[byte as usize]).collect();
+    let mut index = [0u16; 256];
+    used.iter().enumerate().for_each(|(i, &byte)| index[byte as usize] = i as u16);
+    let symbols: Vec<u16> = data.iter().map(|&byte| index[byte as usize]).collect();
+
+    // Without a table count, try every count bzip2 would consider and keep the smallest
+    let candidates = match tables {
+        0 => 1..=table_count(symbols.len()),
+        tables => tables..=tables,
+    };
+    let (output, tail) = candidates
+        .map(|tables| huffman_tables_with(&symbols, used.len(), tables))
+        .min_by_key(|(output, tail)| output.len() + tail.len())
+        .unwrap();
+
+    write_varint(&mut params, used.len() as u64);
+    params.extend_from_slice(&used);
+    params.extend(tail);
+    Ok((output, params))
+}
+
+// The coded symbols, and the table count, code lengths and selectors for the parameters
+fn huffman_tables_with(symbols: &[u16], alpha_size: usize, tables: usize) -> (Vec<u8>, Vec<u8>) {
+    let (lengths, selectors) = choose_tables(symbols, alpha_size, tables, MAX_CODE_LEN);
+
+    let mut writer = BitWriter::new();
+    lengths.iter().flatten().for_each(|&length| writer.write(4, length as u32));
+    // A lone table needs no selectors
+    let mut order: Vec<u8> = (0..tables as u8).collect();
+    for &selector in selectors.iter().filter(|_| tables > 1) {
+        let j = order.iter().position(|&table| table == selector).unwrap();
+        (0..j).for_each(|_| writer.write_bit(true));
+        writer.write_bit(false);
+        order.remove(j);
+        order.insert(0, selector);
+    }
+
+    let mut tail = vec![tables as u8];
+    tail.extend(writer.finish());
+    (huffman_encode_groups(symbols, &lengths, &selectors), tail)
+}
+
+fn huffman_tables_decode(data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let count = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing Huffman symbol count"))? as usize;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    // Every code takes at least one bit
+    if count > data.len() * 8 {
+        return Err(Error::InvalidPayload("truncated Huffman data"));
+    }
+
+    let (used, tables, mut reader) = read_huffman_tables(params, pos)?;
+    let decoders = tables.iter().map(|lengths| HuffmanDecoder::new(lengths)).collect::<Result<Vec<_>>>()?;
+    let tables = tables.len();
+
+    // Undo the move-to-front of every selector as it is read, then decode its group
+    let mut order: Vec<usize> = (0..tables).collect();
+    let (mut output, mut bit_pos) = (Vec::with_capacity(count), 0);
+    while output.len() < count {
+        let mut j = 0;
+        while tables > 1 && reader.read_bit().ok_or(Error::InvalidHeader("truncated Huffman selectors"))? {
+            j += 1;
+            if j >= tables {
+                return Err(Error::InvalidHeader("invalid Huffman selector"));
+            }
+        }
+        let table = order.remove(j);
+        order.insert(0, table);
+
+        let group = GROUP_SIZE.min(count - output.len());
+        decoders[table].decode_into(data, &mut bit_pos, group, &mut output)?;
+    }
+    output
+        .into_iter()
+        .map(|symbol| used.get(symbol as usize).copied())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(Error::InvalidPayload("invalid Huffman symbol"))
+}
+
+// The bytes in use, the code lengths of every table over them, and a reader left at the selectors
+type HuffmanTables<'a> = (&'a [u8], Vec<Vec<u8>>, BitReader<'a>);
+
+fn read_huffman_tables(params: &[u8], mut pos: usize) -> Result<HuffmanTables<'_>> {
+    let n_used = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing Huffman alphabet"))? as usize;
+    let used = params.get(pos..pos.saturating_add(n_used)).ok_or(Error::InvalidHeader("truncated Huffman alphabet"))?;
+    if used.is_empty() || used.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(Error::InvalidHeader("invalid Huffman alphabet"));
+    }
+    pos += n_used;
+
+    let tables = *params.get(pos).ok_or(Error::InvalidHeader("missing Huffman table count"))? as usize;
+    if !(1..=MAX_TABLES).contains(&tables) {
+        return Err(Error::InvalidHeader("invalid Huffman table count"));
+    }
+    let mut reader = BitReader::new(&params[pos + 1..]);
+
+    let mut lengths = vec![Vec::with_capacity(n_used); tables];
+    for table in lengths.iter_mut() {
+        for _ in 0..n_used {
+            let length = reader.read(4).ok_or(Error::InvalidHeader("truncated Huffman code lengths"))?;
+            if length == 0 {
+                return Err(Error::InvalidHeader("invalid Huffman code length"));
+            }
+            table.push(length as u8);
+        }
+    }
+    Ok((used, lengths, reader))
+}
+
+// The code tables a Huffman stage recorded in its parameters, as (byte, code length) pairs for
+// the bytes each table codes; empty when the stage coded nothing
+pub(crate) fn huffman_code_lengths(id: u8, params: &[u8]) -> Result<Vec<Vec<(u8, u8)>>> {
+    let mut pos = 0;
+    let count = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing Huffman symbol count"))?;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    match id {
+        HUFFMAN_ID => {
+            let lengths = read_single_lengths(params, &mut pos)?;
+            Ok(vec![(0..=255).zip(lengths).filter(|&(_, length)| length > 0).collect()])
+        }
+        HUFFMAN_TABLES_ID => {
+            let (used, tables, _) = read_huffman_tables(params, pos)?;
+            Ok(tables.into_iter().map(|lengths| used.iter().copied().zip(lengths).collect()).collect())
+        }
+        _ => Err(Error::InvalidHeader("not a Huffman stage")),
+    }
+}
+
+// One-pass adaptive Huffman coding; the parameters hold only the symbol count
+pub struct AdaptiveHuffman;
+
+impl Stage for AdaptiveHuffman {
+    fn name(&self) -> &'static str {
+        "ahuffman"
+    }
+
+    fn id(&self) -> u8 {
+        ADAPTIVE_HUFFMAN_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Entropy
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut params = Vec::new();
+        write_varint(&mut params, data.len() as u64);
+        Ok((adaptive_huffman_encode(data), params))
+    }
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+        let count = read_varint(params, &mut 0).ok_or(Error::InvalidHeader("missing adaptive Huffman symbol count"))?;
+        let count = usize::try_from(count).map_err(|_| Error::InvalidHeader("invalid adaptive Huffman symbol count"))?;
+        adaptive_huffman_decode(data, count)
+    }
+}
+
+// Binary adaptive range coder; the parameters hold the context order and the symbol count
+pub struct Range {
+    pub order: u8,
+}
+
+impl Default for Range {
+    fn default() -> Self {
+        Range { order: 1 }
+    }
+}
+
+fn read_order(params: &[u8]) -> Result<u8> {
+    params.first().copied().filter(|&order| order <= MAX_ORDER).ok_or(Error::InvalidHeader("invalid range coder order"))
+}
+
+impl Stage for Range {
+    fn name(&self) -> &'static str {
+        "range"
+    }
+
+    fn id(&self) -> u8 {
+        RANGE_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Entropy
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        match self.order {
+            1 => Vec::new(),
+            order => vec![("order", order.to_string())],
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut params = vec![self.order];
+        write_varint(&mut params, data.len() as u64);
+        if data.is_empty() {
+            return Ok((Vec::new(), params));
+        }
+        Ok((range_encode(data, self.order), params))
+    }
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+        let mut pos = 1;
+        let order = read_order(params)?;
+        let count = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing range coder symbol count"))?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let count = usize::try_from(count).map_err(|_| Error::InvalidHeader("invalid range coder symbol count"))?;
+        range_decode(data, count, order)
+    }
+}
+
+// Static rANS coder; the parameters hold the number of interleaved states, the byte count and
+// the normalised frequency of every byte used, as the byte followed by its frequency varint
+pub struct Rans {
+    pub interleaved: bool,
+}
+
+impl Default for Rans {
+    fn default() -> Self {
+        Rans { interleaved: true }
+    }
+}
+
+fn read_ways(params: &[u8]) -> Result<usize> {
+    params
+        .first()
+        .map(|&ways| ways as usize)
+        .filter(|&ways| ways == 1 || ways == INTERLEAVE)
+        .ok_or(Error::InvalidHeader("invalid rANS interleaving"))
+}
+
+impl Stage for Rans {
+    fn name(&self) -> &'static str {
+        "rans"
+    }
+
+    fn id(&self) -> u8 {
+        RANS_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Entropy
+    }
+
+    fn options(&self) -> Vec<(&'static str, String)> {
+        match self.interleaved {
+            true => Vec::new(),
+            false => vec![("interleave", "false".to_string())],
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let ways = if self.interleaved { INTERLEAVE } else { 1 };
+        let mut params = vec![ways as u8];
+        write_varint(&mut params, data.len() as u64);
+        if data.is_empty() {
+            return Ok((Vec::new(), params));
+        }
+
+        let mut counts = [0u32; 256];
+        data.iter().for_each(|&byte| counts[byte as usize] += 1);
+        let mut freqs = normalize_frequencies(&counts);
+        // A lone byte codes for free, but only up to MAX_FREE_COUNT of it; past that an unused
+        // neighbour takes one slot, which costs the lone byte about a bit every 2800
+        if data.len() > MAX_FREE_COUNT && freqs[data[0] as usize] == 1 << SCALE_BITS {
+            freqs[data[0] as usize] -= 1;
+            freqs[data[0].wrapping_add(1) as usize] = 1;
+        }
+        write_varint(&mut params, freqs.iter().filter(|&&freq| freq > 0).count() as u64);
+        for (byte, &freq) in freqs.iter().enumerate().filter(|(_, &freq)| freq > 0) {
+            params.push(byte as u8);
+            write_varint(&mut params, freq as u64);
+        }
+        Ok((rans_encode(data, &freqs, ways), params))
+    }
+
+    fn decode(&self, data: &[u8], params: &[u8]) -> Result<Vec<u8>> {
+        let ways = read_ways(params)?;
+        let mut pos = 1;
+        let count = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("missing rANS symbol count"))?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let count = usize::try_from(count).map_err(|_| Error::InvalidHeader("invalid rANS symbol count"))?;
+
+        let n_used = read_varint(params, &mut pos)
+            .filter(|&n_used| n_used <= 256)
+            .ok_or(Error::InvalidHeader("invalid rANS frequencies"))?;
+        let mut freqs = vec![0u32; 256];
+        let mut previous = None;
+        for _ in 0..n_used {
+            let &byte = params.get(pos).ok_or(Error::InvalidHeader("truncated rANS frequencies"))?;
+            pos += 1;
+            let freq = read_varint(params, &mut pos).ok_or(Error::InvalidHeader("truncated rANS frequencies"))?;
+            if previous.is_some_and(|previous| byte <= previous) || freq == 0 || freq > u32::MAX as u64 {
+                return Err(Error::InvalidHeader("invalid rANS frequencies"));
+            }
+            freqs[byte as usize] = freq as u32;
+            previous = Some(byte);
+        }
+        rans_decode(data, &freqs, count, ways)
+    }
+}
+
+// Text encoder: RFC 1924 base85 as provided by the base85 crate
+pub struct Base85;
+
+impl Stage for Base85 {
+    fn name(&self) -> &'static str {
+        "base85"
+    }
+
+    fn id(&self) -> u8 {
+        BASE85_ID
+    }
+
+    fn kind(&self) -> StageKind {
+        StageKind::Armor
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        Ok((base85::encode(data).into_bytes(), Vec::new()))
+    }
+
+    fn decode(&self, data: &[u8], _params: &[u8]) -> Result<Vec<u8>> {
+        let text = std::str::from_utf8(data).map_err(|_| Error::InvalidArmor)?;
+        base85_decode(text)
+    }
+}
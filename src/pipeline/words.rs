@@ -0,0 +1,26 @@
+// Word list of the words armor, one word per byte value. The words are common, three to seven
+// letters long and sorted, so a word is looked up by binary search.
+pub(super) static WORDS: [&str; 256] = [
+    "acid", "acorn", "actor", "adobe", "agent", "alarm", "album", "alpha", "amber", "angle", "ankle", "apple", "apron",
+    "arena", "arrow", "aspen", "atlas", "attic", "autumn", "avenue", "bacon", "badge", "bagel", "baker", "bamboo",
+    "banjo", "barn", "basin", "beach", "beard", "beetle", "bell", "bench", "berry", "bike", "birch", "bison", "blade",
+    "bloom", "board", "bonus", "boot", "bottle", "brass", "bread", "brick", "bridge", "broom", "bucket", "bugle",
+    "butter", "cabin", "cactus", "camel", "candle", "canoe", "canyon", "carbon", "cargo", "carpet", "carrot", "castle",
+    "cattle", "cedar", "cello", "chalk", "cherry", "chess", "cider", "circus", "citrus", "clock", "cloud", "clover",
+    "cobra", "cocoa", "comet", "copper", "coral", "cotton", "coyote", "crane", "crater", "cup", "dagger", "daisy",
+    "dance", "delta", "desert", "dinner", "dollar", "donkey", "dragon", "drum", "eagle", "earth", "echo", "elbow",
+    "ember", "engine", "falcon", "fence", "ferry", "fiddle", "finger", "flame", "flute", "forest", "fossil", "fox",
+    "galaxy", "garden", "garlic", "geyser", "ginger", "globe", "goblet", "gold", "grape", "gravel", "guitar", "hammer",
+    "harbor", "hazel", "helmet", "hermit", "hiking", "honey", "hornet", "hotel", "husky", "igloo", "indigo", "insect",
+    "island", "ivory", "jacket", "jaguar", "jelly", "jewel", "jungle", "kayak", "kernel", "kettle", "kitten", "koala",
+    "ladder", "lagoon", "lantern", "laser", "lemon", "lily", "lizard", "llama", "locket", "lotus", "lumber", "magnet",
+    "mango", "maple", "marble", "meadow", "melon", "meteor", "mirror", "mitten", "monkey", "moose", "mosaic", "muffin",
+    "nectar", "needle", "nickel", "noodle", "nugget", "oasis", "ocean", "olive", "onion", "opera", "orange", "orbit",
+    "orchid", "otter", "owl", "oyster", "paddle", "palace", "panda", "parrot", "peach", "peanut", "pebble", "pencil",
+    "pepper", "piano", "pickle", "pilot", "pine", "pirate", "planet", "plum", "pocket", "pony", "poppy", "potato",
+    "puzzle", "quartz", "quilt", "rabbit", "radar", "radish", "raven", "ribbon", "river", "robin", "rocket", "saddle",
+    "salmon", "sandal", "satin", "scarf", "seal", "shovel", "silver", "sketch", "sled", "snail", "spider", "spoon",
+    "statue", "storm", "sugar", "summit", "sunset", "swan", "table", "tiger", "toast", "tomato", "tulip", "tunnel",
+    "turtle", "valley", "velvet", "violin", "wagon", "walnut", "whale", "willow", "window", "winter", "wizard",
+    "yogurt", "zebra", "zipper",
+];
@@ -0,0 +1,167 @@
+use std::io::{Read, Write};
+
+use message_obfuscation::diropql::stream::split_blobs;
+use message_obfuscation::diropql::zip::{read_diropqlz_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::{Armor, Pipeline};
+use message_obfuscation::{DiropqlzReader, DiropqlzWriter, Error};
+
+#[test]
+fn known_encodings() {
+    // RFC 4648 section 10
+    let rfc = ["", "f", "fo", "foo", "foob", "fooba", "foobar"];
+    let base64 = ["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"];
+    let base32 = ["", "MY", "MZXQ", "MZXW6", "MZXW6YQ", "MZXW6YTB", "MZXW6YTBOI"];
+    let hex = ["", "66", "666f", "666f6f", "666f6f62", "666f6f6261", "666f6f626172"];
+    for i in 0..rfc.len() {
+        assert_eq!(Armor::Base64.encode(rfc[i].as_bytes()), base64[i]);
+        assert_eq!(Armor::Base64Url.encode(rfc[i].as_bytes()), base64[i].trim_end_matches('='));
+        assert_eq!(Armor::Base32.encode(rfc[i].as_bytes()), base32[i]);
+        assert_eq!(Armor::Hex.encode(rfc[i].as_bytes()), hex[i]);
+    }
+    assert_eq!(Armor::Base64Url.encode(&[0xfb, 0xff]), "-_8");
+    assert_eq!(Armor::Base64.encode(&[0xfb, 0xff]), "+/8=");
+
+    assert_eq!(Armor::Base58.encode(b"Hello World!"), "2NEpo7TZRRrLZSi2U");
+    assert_eq!(Armor::Base58.encode(&[0, 0, 0x28, 0x7f, 0xb4, 0xcd]), "11233QC4");
+    // The example from the Z85 specification
+    assert_eq!(Armor::Z85.encode(&[0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b]), "HelloWorld");
+    assert_eq!(Armor::Ascii85.encode(b"Man "), "<~9jqo^~>");
+    assert_eq!(Armor::Ascii85.encode(&[0, 0, 0, 0, b'M']), "<~z9`~>");
+
+    // A 12-letter byte count, then three letters for a lone byte: 255 is 5 1 3 in base 7
+    assert_eq!(Armor::Base7 { wrap: 0 }.encode(&[]), "dddddddddddd");
+    assert_eq!(Armor::Base7 { wrap: 0 }.encode(&[255]), "dddddddddddiqio");
+    assert_eq!(Armor::Base7 { wrap: 0 }.encode(&[0; 7]).len(), 12 + 20);
+    assert_eq!(Armor::Base7 { wrap: 5 }.encode(&[255]), "ddddd\nddddd\ndiqio");
+
+    // One word per byte, then the CRC-8 of the bytes and their count
+    assert_eq!(Armor::Words.encode(&[]), "acid");
+    assert_eq!(Armor::Words.encode(&[0, 255]), "acid zipper boot");
+}
+
+#[test]
+fn round_trip() {
+    let data: Vec<u8> = (0..300u32).map(|i| (i * 7919 % 256) as u8).collect();
+    for armor in Armor::ALL {
+        assert_eq!(Armor::from_name(armor.name()), Some(armor));
+        for len in (0..40).chain([255, 300]) {
+            let mut sample = data[..len].to_vec();
+            // Leading and trailing zeros are special in base58 and Ascii85
+            if len % 3 == 0 {
+                sample.iter_mut().take(len / 3).for_each(|byte| *byte = 0);
+            }
+            let text = armor.encode(&sample);
+            assert_eq!(armor.decode(&text).unwrap(), sample, "{} of {} bytes", armor.name(), len);
+        }
+    }
+
+    // Case folding and line breaks on the way do not matter where they cannot change the meaning
+    assert_eq!(Armor::Base32.decode("mzxw6\nytboi").unwrap(), b"foobar");
+    assert_eq!(Armor::Hex.decode("666F6F").unwrap(), b"foo");
+    assert_eq!(Armor::Words.decode("  Acid\nZIPPER\tboot ").unwrap(), [0, 255]);
+}
+
+#[test]
+fn rejects_bad_text() {
+    let bad = [
+        (Armor::Base64, "Zm9v!"),
+        (Armor::Base64, "Zm9vY"),
+        (Armor::Base64, "Zh=="),
+        (Armor::Base64Url, "+/8"),
+        (Armor::Base32, "MZ1"),
+        (Armor::Base58, "0OIl"),
+        (Armor::Z85, "Hello\""),
+        (Armor::Z85, "%%%%%"),
+        (Armor::Ascii85, "9jqo^"),
+        (Armor::Ascii85, "<~9jqo^v~>"),
+        (Armor::Hex, "abc"),
+        (Armor::Hex, "xy"),
+        (Armor::Base85, "\"\""),
+        (Armor::Base7 { wrap: 0 }, "dddddddddddiqi"),
+        (Armor::Base7 { wrap: 0 }, "dddddddddddiqiod"),
+        (Armor::Base7 { wrap: 0 }, "dddddddddddilll"),
+        (Armor::Base7 { wrap: 0 }, "dddddddddddiqix"),
+        (Armor::Base7 { wrap: 0 }, "ddddd"),
+        (Armor::Words, ""),
+        (Armor::Words, "acid zipper boo"),
+        (Armor::Words, "acid zipper boot acid"),
+        (Armor::Words, "zipper acid boot"),
+        (Armor::Words, "acidzipper boot"),
+    ];
+    for (armor, text) in bad {
+        assert_eq!(armor.decode(text), Err(Error::InvalidArmor), "{} {}", armor.name(), text);
+    }
+}
+
+#[test]
+fn blobs_carry_their_armor() {
+    let message = &include_bytes!("../README.md")[..1000];
+    for armor in Armor::ALL {
+        let pipeline = Pipeline::default().with_armor(armor);
+        let blob = write_diropqlz_with(message, &pipeline).unwrap();
+        assert!(blob.starts_with(&armor.magic()));
+        assert_eq!(Armor::detect(&blob).unwrap().0, armor);
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message, "{}", armor.name());
+    }
+
+    // Letters and digits only, for channels that reject punctuation
+    for armor in [Armor::Base32, Armor::Base58, Armor::Hex, Armor::Base7 { wrap: 0 }] {
+        let blob = write_diropqlz_with(b"hello", &Pipeline::default().with_armor(armor)).unwrap();
+        assert!(blob.bytes().all(|byte| byte.is_ascii_alphanumeric()), "{}", blob);
+    }
+    let blob = write_diropqlz_with(b"hello", &Pipeline::default().with_armor(Armor::Words)).unwrap();
+    assert!(blob.starts_with("DIROPQLW ") && blob.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b' '));
+    assert_eq!(read_diropqlz_bytes("DIROPQLX0000"), Err(Error::MissingMagic));
+    assert_eq!(read_diropqlz_bytes("DIROPQLHzz"), Err(Error::InvalidArmor));
+}
+
+#[test]
+fn armor_in_descriptions() {
+    let pipeline: Pipeline = "diropql|bwt|mtf|rle|huffman|base32".parse().unwrap();
+    assert_eq!(pipeline.armor(), Armor::Base32);
+    assert_eq!(pipeline.to_string(), "diropql|bwt:block=256k|mtf|rle|huffman|base32");
+    assert_eq!("rle|base85".parse::<Pipeline>().unwrap().armor(), Armor::Base85);
+
+    // base85 stays a stage when another armor follows it
+    let pipeline: Pipeline = "rle|base85|hex".parse().unwrap();
+    assert_eq!((pipeline.stages().len(), pipeline.armor()), (2, Armor::Hex));
+    assert!("rle|hex|base85".parse::<Pipeline>().is_err());
+    assert!("hex".parse::<Pipeline>().unwrap().stages().is_empty());
+
+    let pipeline: Pipeline = "rle|base7:wrap=60".parse().unwrap();
+    assert_eq!(pipeline.armor(), Armor::Base7 { wrap: 60 });
+    assert_eq!(pipeline.to_string(), "rle|base7:wrap=60");
+    assert_eq!("rle|base7:wrap=0".parse::<Pipeline>().unwrap().to_string(), "rle|base7");
+    assert!("rle|base7:wrap=x".parse::<Pipeline>().is_err());
+    assert!("rle|hex:wrap=60".parse::<Pipeline>().is_err());
+}
+
+#[test]
+fn wrapped_blobs_in_streams() {
+    // Only the diropql letters, over lines of 50, and the streams still split into blobs
+    let message = &include_bytes!("../README.md")[..3000];
+    let pipeline: Pipeline = "diropql|bwt|mtf|rle|huffman|base7:wrap=50".parse().unwrap();
+    let mut writer = DiropqlzWriter::with_pipeline(Vec::new(), pipeline, 1000);
+    writer.write_all(message).unwrap();
+    let stream = String::from_utf8(writer.finish().unwrap()).unwrap();
+
+    assert!(stream.lines().skip(1).all(|line| line.starts_with("DIROPQLD") || line.len() <= 50));
+    assert!(stream.lines().flat_map(|line| line.trim_start_matches("DIROPQLD").bytes()).all(|byte| b"diropql".contains(&byte)));
+    let blobs = split_blobs(&stream);
+    assert_eq!(blobs.len(), 3);
+    assert_eq!(blobs.iter().map(|blob| read_diropqlz_bytes(blob).unwrap()).collect::<Vec<_>>().concat(), message);
+
+    let mut decoded = Vec::new();
+    DiropqlzReader::new(stream.as_bytes()).read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, message);
+
+    // Words broken over lines by hand stay apart
+    let mut writer = DiropqlzWriter::with_pipeline(Vec::new(), Pipeline::default().with_armor(Armor::Words), 1000);
+    writer.write_all(message).unwrap();
+    let stream = String::from_utf8(writer.finish().unwrap()).unwrap().replace("e a", "e\na");
+    assert!(stream.lines().count() > 3);
+    assert_eq!(split_blobs(&stream).len(), 3);
+    let mut decoded = Vec::new();
+    DiropqlzReader::new(stream.as_bytes()).read_to_end(&mut decoded).unwrap();
+    assert_eq!(decoded, message);
+}
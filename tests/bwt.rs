@@ -0,0 +1,112 @@
+use message_obfuscation::compressor::bwt::{
+    bwt_decode, bwt_encode_bytes, bwts_decode, bwts_encode, inverse_bwt_lf, inverse_bwt_packed,
+};
+use message_obfuscation::diropql::zip::{read_diropqlz_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::Pipeline;
+
+mod common;
+
+use common::noise;
+
+// The original inverse works on chars; bytes map onto the first 256 of them in the same order
+fn inverse_bwt_chars(last_column: &[u8], index: usize) -> Vec<u8> {
+    let text: String = last_column.iter().map(|&byte| byte as char).collect();
+    bwt_decode(&text, index).chars().map(|c| c as u8).collect()
+}
+
+#[test]
+fn inverses_agree() {
+    let inputs: Vec<Vec<u8>> = vec![
+        Vec::new(),
+        b"x".to_vec(),
+        vec![0],
+        (0..=255).collect(),
+        (0..=255).rev().collect(),
+        vec![b'a'; 100_000],
+        b"ab".repeat(5000),
+        include_bytes!("../README.md").to_vec(),
+        noise(20_000, 5),
+    ];
+
+    for data in inputs {
+        let (last_column, index) = bwt_encode_bytes(&data);
+        let mut expected = data.clone();
+        expected.push(0);
+
+        assert_eq!(inverse_bwt_lf(&last_column, index), expected, "{} bytes", data.len());
+        assert_eq!(inverse_bwt_packed(&last_column, index), expected, "{} bytes", data.len());
+        assert_eq!(inverse_bwt_chars(&last_column, index), expected, "{} bytes", data.len());
+    }
+}
+
+// Every string over the alphabet up to the given length, shortest first
+fn all_strings(alphabet: &[u8], max_len: usize) -> Vec<Vec<u8>> {
+    let mut strings = vec![Vec::new()];
+    let mut last = vec![Vec::new()];
+    for _ in 0..max_len {
+        last = last.iter().flat_map(|s: &Vec<u8>| alphabet.iter().map(move |&c| [s.as_slice(), &[c]].concat())).collect();
+        strings.extend(last.iter().cloned());
+    }
+    strings
+}
+
+#[test]
+fn bwts_round_trip() {
+    let inputs: Vec<Vec<u8>> = vec![
+        Vec::new(),
+        b"x".to_vec(),
+        b"abab".to_vec(),
+        b"aaaa".to_vec(),
+        b"ba".to_vec(),
+        b"banana".to_vec(),
+        b"abcabcabcab".to_vec(),
+        (0..=255).collect(),
+        vec![0; 10_000],
+        include_bytes!("../README.md").to_vec(),
+        noise(20_000, 9),
+    ];
+    for data in inputs {
+        assert_eq!(bwts_decode(&bwts_encode(&data)), data, "{:?}", &data[..data.len().min(16)]);
+    }
+    assert_eq!(bwts_encode(b"abab"), b"bbaa");
+}
+
+#[test]
+fn bwts_is_a_bijection() {
+    // Any byte string is the output of exactly one input
+    for data in [noise(1, 1), noise(2, 2), noise(1000, 3), noise(20_000, 4), b"zzzyyyxxx".to_vec()] {
+        assert_eq!(bwts_encode(&bwts_decode(&data)), data, "{} bytes", data.len());
+    }
+
+    // Exhaustively on short strings: encoding is one to one and decoding undoes it
+    let strings = all_strings(b"abc", 7);
+    let mut outputs: Vec<Vec<u8>> = strings.iter().map(|s| bwts_encode(s)).collect();
+    for (data, output) in strings.iter().zip(&outputs) {
+        assert_eq!(&bwts_decode(output), data);
+        assert_eq!(&bwts_encode(&bwts_decode(data)), data);
+    }
+    outputs.sort();
+    outputs.dedup();
+    assert_eq!(outputs.len(), strings.len());
+}
+
+#[test]
+fn bijective_stage_round_trip() {
+    let text = include_bytes!("../README.md");
+    for spec in [
+        "diropql|bwt:block=4k,bijective=true|mtf|rle|huffman",
+        "bwt:block=100,bijective=true|mtf:alphabet=all|rle",
+        "bwt:bijective=true",
+    ] {
+        let pipeline: Pipeline = spec.parse().unwrap();
+        for data in [&text[..], b"abab", b"x", b""] {
+            let blob = write_diropqlz_with(data, &pipeline).unwrap();
+            assert_eq!(read_diropqlz_bytes(&blob).unwrap(), data, "{}", spec);
+        }
+    }
+
+    // Without primary indices the parameters are just the block size
+    let pipeline: Pipeline = "bwt:block=100,bijective=true".parse().unwrap();
+    let (_, params) = pipeline.encode(&text[..1000]).unwrap();
+    assert_eq!(params[0].len(), 1);
+}
@@ -0,0 +1,93 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use message_obfuscation::compressor::bzip2::{bzip2_decode, bzip2_encode};
+
+mod common;
+
+use common::{noise, runs};
+
+// Runs the system bzip2 with the given flags over input, None when it is not installed
+fn system_bzip2(flags: &[&str], input: &[u8]) -> Option<Vec<u8>> {
+    let mut child = match Command::new("bzip2").args(flags).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(_) => {
+            eprintln!("bzip2 not found, skipping");
+            return None;
+        }
+    };
+
+    // Write from another thread so a full stdout pipe cannot deadlock us
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "bzip2 {:?} failed", flags);
+    Some(output.stdout)
+}
+
+fn samples() -> Vec<(&'static str, Vec<u8>)> {
+    let mut samples = common::samples();
+    samples.extend([
+        ("runs", runs(&[1, 3, 4, 5, 255, 256, 259, 1000, 4, 2])),
+        ("periodic", b"ab".repeat(30_000)),
+        ("long text", include_bytes!("../README.md").repeat(20)),
+    ]);
+    samples
+}
+
+#[test]
+fn system_bzip2_reads_our_streams() {
+    for (name, data) in samples() {
+        let encoded = bzip2_encode(&data, 9);
+        let Some(decoded) = system_bzip2(&["-d", "-c"], &encoded) else { return };
+        assert_eq!(decoded, data, "{}", name);
+    }
+}
+
+#[test]
+fn we_read_system_bzip2_streams() {
+    for (name, data) in samples() {
+        let Some(encoded) = system_bzip2(&["-c", "-9"], &data) else { return };
+        assert_eq!(bzip2_decode(&encoded).unwrap(), data, "{}", name);
+    }
+}
+
+#[test]
+fn multiple_blocks_both_ways() {
+    // Level 1 blocks hold just under 100k, so this spans several of them
+    let mut data = noise(150_000, 3);
+    data.extend(include_bytes!("../README.md").repeat(40));
+
+    let encoded = bzip2_encode(&data, 1);
+    assert_eq!(bzip2_decode(&encoded).unwrap(), data);
+    if let Some(decoded) = system_bzip2(&["-d", "-c"], &encoded) {
+        assert_eq!(decoded, data);
+    }
+    if let Some(encoded) = system_bzip2(&["-c", "-1"], &data) {
+        assert_eq!(bzip2_decode(&encoded).unwrap(), data);
+    }
+}
+
+#[test]
+fn concatenated_streams() {
+    let mut encoded = bzip2_encode(b"hello ", 9);
+    encoded.extend(bzip2_encode(b"world", 9));
+    assert_eq!(bzip2_decode(&encoded).unwrap(), b"hello world");
+}
+
+#[test]
+fn corruption_is_detected() {
+    let data = include_bytes!("../README.md");
+    let encoded = bzip2_encode(data, 9);
+
+    assert!(bzip2_decode(&encoded[..encoded.len() / 2]).is_err());
+    assert!(bzip2_decode(b"BZh9").is_err());
+    assert!(bzip2_decode(b"not bzip2").is_err());
+
+    let mut flipped = encoded.clone();
+    flipped[encoded.len() / 2] ^= 0x10;
+    assert!(bzip2_decode(&flipped).is_err());
+}
@@ -0,0 +1,73 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn diropqlz(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_diropqlz"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn encode_then_decode() {
+    let encoded = diropqlz(&["encode"], b"hello\n");
+    assert!(encoded.status.success());
+    assert!(encoded.stdout.starts_with(b"DIROPQLZ"));
+
+    let decoded = diropqlz(&["decode", "-"], &encoded.stdout);
+    assert!(decoded.status.success());
+    assert_eq!(decoded.stdout, b"hello\n");
+
+    let compiled = diropqlz(&["compile"], b"hi");
+    assert_eq!(diropqlz(&["run"], &compiled.stdout).stdout, b"hi");
+    assert!(diropqlz(&["help"], b"").status.success());
+}
+
+#[test]
+fn compile_then_run_long_text() {
+    // Longer than diropql memory, so the program has to reuse cells
+    let text = include_str!("../README.md").repeat(3);
+    let compiled = diropqlz(&["compile"], text.as_bytes());
+    assert!(compiled.status.success());
+    let run = diropqlz(&["run"], &compiled.stdout);
+    assert!(run.status.success());
+    assert_eq!(run.stdout, text.as_bytes());
+}
+
+#[test]
+fn usage_mistakes_exit_with_2() {
+    for args in [
+        &[][..],
+        &["frobnicate"],
+        &["encode", "--bogus"],
+        &["encode", "-p"],
+        &["encode", "-p", "huffman|bwt"],
+        &["decode", "-p", "rle"],
+        &["decode", "-a", "hex"],
+        &["encode", "a.txt", "b.txt"],
+        &["search"],
+    ] {
+        let output = diropqlz(args, b"");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"), "{:?}", args);
+    }
+}
+
+#[test]
+fn failures_exit_with_1() {
+    for (args, input) in [
+        (&["decode"][..], &b"DIROPQLZ!!!!\n"[..]),
+        (&["decode"], b"not a blob\n"),
+        (&["inspect"], b"DIROPQLHzz\n"),
+        (&["decode", "/nonexistent/input"], b""),
+    ] {
+        let output = diropqlz(args, input);
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "), "{:?}", args);
+    }
+}
@@ -0,0 +1,43 @@
+// Fixtures shared by the integration tests; not every test uses all of them
+#![allow(dead_code)]
+
+// Deterministic noise so failures reproduce
+pub fn noise(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+// Inputs every byte coder has to get right; the tests add their own special cases
+pub fn samples() -> Vec<(&'static str, Vec<u8>)> {
+    vec![
+        ("empty", Vec::new()),
+        ("one byte", b"x".to_vec()),
+        ("all bytes", (0..=255).collect()),
+        ("zeros", vec![0; 70_000]),
+        ("text", include_bytes!("../../README.md").to_vec()),
+        ("noise", noise(50_000, 7)),
+    ]
+}
+
+// Fibonacci frequencies give the deepest possible Huffman tree
+pub fn fibonacci(n: usize) -> Vec<u32> {
+    let mut freqs = vec![1u32, 1];
+    while freqs.len() < n {
+        freqs.push(freqs[freqs.len() - 1] + freqs[freqs.len() - 2]);
+    }
+    freqs
+}
+
+// Runs of every length around the limits of the run-length coders, each of another byte
+pub fn runs(lengths: &[usize]) -> Vec<u8> {
+    let mut runs = Vec::new();
+    for (i, &len) in lengths.iter().enumerate() {
+        runs.extend(std::iter::repeat_n(b'a' + i as u8, len));
+    }
+    runs
+}
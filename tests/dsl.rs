@@ -0,0 +1,93 @@
+use message_obfuscation::pipeline::{Armor, Pipeline};
+use message_obfuscation::Error;
+
+#[test]
+fn descriptions_round_trip() {
+    let canonical = [
+        "diropql|bwt:block=256k|mtf|rle|huffman|base85",
+        "bwt:block=4k,bijective=true|base85",
+        "bwt:block=1000|base85",
+        "mtf:alphabet=all|base85",
+        "mtf:alphabet=auto|base85",
+        "mtf:alphabet=abc|base85",
+        "mtf:alphabet=a\\x7cb\\x00|base85",
+        "mtf:variant=mtf1|mtf:variant=mtf2|mtf:variant=ts0|base85",
+        "mtf:variant=wfc,alphabet=all|mtf:variant=if|base85",
+        "rle|packbits|rle1|base85",
+        "diropql|cmdrle|base85",
+        "huffman:tables=1|base85",
+        "huffman:tables=3|base85",
+        "ahuffman|base85",
+        "range|base85",
+        "range:order=0|base85",
+        "rans|base85",
+        "rans:interleave=false|base85",
+        "rle|base85|base85",
+    ];
+    for description in canonical {
+        let pipeline: Pipeline = description.parse().unwrap();
+        assert_eq!(pipeline.to_string(), description);
+        assert_eq!(pipeline.to_string().parse::<Pipeline>().unwrap().to_string(), description);
+    }
+
+    // Other spellings of the same pipeline print the canonical one
+    let spellings = [
+        ("bwt", "bwt:block=256k|base85"),
+        ("bwt:block=262144", "bwt:block=256k|base85"),
+        ("bwt:block=1M", "bwt:block=1m|base85"),
+        ("bwt : block = 4k , bijective = false", "bwt:block=4k|base85"),
+        ("huffman:tables=auto", "huffman|base85"),
+        ("mtf:alphabet=\\x00diropql", "mtf|base85"),
+        ("diropql\n# a comment\nbwt:block=4k   # and another\n\nmtf|rle", "diropql|bwt:block=4k|mtf|rle|base85"),
+    ];
+    for (description, expected) in spellings {
+        assert_eq!(description.parse::<Pipeline>().unwrap().to_string(), expected);
+    }
+}
+
+#[test]
+fn trailing_armor_is_the_armor() {
+    let pipeline: Pipeline = "diropql|rle|base32".parse().unwrap();
+    assert_eq!((pipeline.stages().len(), pipeline.armor()), (2, Armor::Base32));
+
+    let pipeline: Pipeline = "diropql|rle|base85".parse().unwrap();
+    assert_eq!((pipeline.stages().len(), pipeline.armor()), (2, Armor::Base85));
+
+    let pipeline: Pipeline = "rle|base85|words".parse().unwrap();
+    assert_eq!(pipeline.stages().iter().map(|stage| stage.name()).collect::<Vec<_>>(), ["rle", "base85"]);
+    assert_eq!(pipeline.armor(), Armor::Words);
+}
+
+#[test]
+fn malformed_descriptions_are_errors() {
+    let malformed = [
+        "",
+        " | ",
+        "# only a comment",
+        "frobnicate",
+        "rle|frobnicate",
+        "bwt:block=0",
+        "bwt:block=12q",
+        "bwt:block=",
+        "bwt:block=99999999999999999999k",
+        "bwt:block",
+        "bwt:size=4k",
+        "bwt:bijective=yes",
+        "rle:fast=true",
+        "huffman|bwt",
+        "rans|mtf",
+        "rle|diropql",
+        "mtf:alphabet=aa",
+        "mtf:alphabet=\\xzz",
+        "mtf:variant=mtf9",
+        "mtf:variant=if,alphabet=all",
+        "huffman:tables=7",
+        "range:order=2",
+        "rans:interleave=2",
+        "hex|rle",
+        "rle|base7:wrap=x",
+    ];
+    for description in malformed {
+        assert!(matches!(description.parse::<Pipeline>(), Err(Error::InvalidPipeline(_))), "{:?}", description);
+    }
+}
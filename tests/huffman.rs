@@ -0,0 +1,148 @@
+use message_obfuscation::compressor::adaptive_huffman::{adaptive_huffman_decode, adaptive_huffman_encode};
+use message_obfuscation::compressor::bits::BitWriter;
+use std::collections::HashMap;
+
+use message_obfuscation::compressor::huffman::{
+    build_canonical_codebook, build_codebook, build_huffman_tree, canonical_codes, codebook_entries, entries_to_dot,
+    entries_to_json, huffman_encode, limited_code_lengths, tree_entries, HuffmanDecoder, MAX_CODE_LEN,
+};
+use message_obfuscation::diropql::zip::{inspect_huffman, read_diropqlz_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::Pipeline;
+
+mod common;
+
+use common::fibonacci;
+
+fn encode_symbols(symbols: &[u16], lengths: &[u8]) -> Vec<u8> {
+    let codes = canonical_codes(lengths);
+    let mut writer = BitWriter::new();
+    for &symbol in symbols {
+        writer.write(lengths[symbol as usize] as u32, codes[symbol as usize]);
+    }
+    writer.finish()
+}
+
+#[test]
+fn table_decoder_round_trip() {
+    // Short codes that hit the lookup table and long ones past it
+    for (n, max_len) in [(2, 15), (12, 15), (30, MAX_CODE_LEN), (30, 24), (300, 20)] {
+        let freqs = fibonacci(n.min(30)).into_iter().cycle().take(n).collect::<Vec<u32>>();
+        let lengths = limited_code_lengths(&freqs, max_len).unwrap();
+        let symbols: Vec<u16> = (0..n as u16).chain((0..2000).map(|i| (i * 7 % n) as u16)).collect();
+
+        let decoder = HuffmanDecoder::new(&lengths).unwrap();
+        let encoded = encode_symbols(&symbols, &lengths);
+        assert_eq!(decoder.decode(&encoded, symbols.len()).unwrap(), symbols, "{} symbols", n);
+    }
+}
+
+#[test]
+fn table_decoder_reads_huffman_encode() {
+    let data = include_bytes!("../README.md");
+    let mut freqs = [0u32; 256];
+    data.iter().for_each(|&byte| freqs[byte as usize] += 1);
+    let lengths = limited_code_lengths(&freqs, 9).unwrap();
+
+    let decoded = HuffmanDecoder::new(&lengths).unwrap().decode(&huffman_encode(data, &lengths), data.len()).unwrap();
+    assert_eq!(decoded.iter().map(|&symbol| symbol as u8).collect::<Vec<u8>>(), data);
+}
+
+#[test]
+fn table_decoder_rejects_bad_input() {
+    assert!(HuffmanDecoder::new(&[1, 1, 1]).is_err());
+    assert!(HuffmanDecoder::new(&[33]).is_err());
+
+    // Incomplete code: only 0 and 10 exist, so 11 is invalid
+    let decoder = HuffmanDecoder::new(&[1, 2]).unwrap();
+    assert_eq!(decoder.decode(&[0b0100_0000], 2).unwrap(), vec![0, 1]);
+    assert!(decoder.decode(&[0b1100_0000], 1).is_err());
+    assert!(decoder.decode(&[0b1010_1010], 5).is_err());
+    assert!(decoder.decode(&[], 1).is_err());
+}
+
+#[test]
+fn stage_round_trip_with_tables() {
+    // Statistics that shift halfway through favour two tables
+    let mut message = include_bytes!("../README.md").to_vec();
+    message.extend((0..20_000u32).map(|i| (i * 7919 % 251) as u8));
+
+    for spec in ["huffman", "huffman:tables=1", "huffman:tables=2", "huffman:tables=6", "bwt:block=256k|mtf:alphabet=all|rle|huffman:tables=3"] {
+        let pipeline: Pipeline = spec.parse().unwrap();
+        assert_eq!(pipeline.to_string(), format!("{}|base85", spec));
+
+        let blob = write_diropqlz_with(&message, &pipeline).unwrap();
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message, "{}", spec);
+        assert_eq!(read_diropqlz_bytes(&write_diropqlz_with(b"x", &pipeline).unwrap()).unwrap(), b"x");
+        assert_eq!(read_diropqlz_bytes(&write_diropqlz_with(b"", &pipeline).unwrap()).unwrap(), b"");
+    }
+    assert!("huffman:tables=7".parse::<Pipeline>().is_err());
+}
+
+#[test]
+fn adaptive_round_trip() {
+    let text = include_bytes!("../README.md");
+    let samples: [&[u8]; 5] = [b"", b"a", b"aaaaaaaa", &(0..=255).rev().collect::<Vec<u8>>(), text];
+    for data in samples {
+        let encoded = adaptive_huffman_encode(data);
+        assert_eq!(adaptive_huffman_decode(&encoded, data.len()).unwrap(), data);
+    }
+
+    // A lone byte costs its 8 bits, after which every repeat takes a single bit
+    assert_eq!(adaptive_huffman_encode(b"aaaaaaaaa"), vec![b'a', 0]);
+    assert!(adaptive_huffman_decode(&adaptive_huffman_encode(text)[..100], text.len()).is_err());
+
+    let pipeline: Pipeline = "diropql|bwt|mtf|rle|ahuffman".parse().unwrap();
+    let blob = write_diropqlz_with(text, &pipeline).unwrap();
+    assert_eq!(read_diropqlz_bytes(&blob).unwrap(), text);
+}
+
+#[test]
+fn tree_export() {
+    let message = b"abracadabra";
+    let tree = build_huffman_tree(message);
+    let mut codebook = HashMap::new();
+    build_codebook(&tree, &mut String::new(), &mut codebook);
+
+    let entries = tree_entries(&tree);
+    assert_eq!(entries.len(), 5);
+    for entry in &entries {
+        assert_eq!(codebook[&entry.symbol], entry.code);
+        assert_eq!(entry.frequency, message.iter().filter(|&&byte| byte == entry.symbol).count() as u64);
+    }
+
+    // Five leaves and four inner nodes, the root carrying the whole message
+    let dot = entries_to_dot(&entries);
+    assert!(dot.starts_with("digraph huffman {\n") && dot.ends_with("}\n"));
+    assert!(dot.contains("n0 [label=\"11\"];"));
+    assert_eq!(dot.matches("shape=box").count(), 5);
+    assert_eq!(dot.matches(" -> ").count(), 8);
+    assert!(dot.contains("'a'\\n5\\n"));
+
+    let mut freqs = [0u32; 256];
+    message.iter().for_each(|&byte| freqs[byte as usize] += 1);
+    let canonical = codebook_entries(&build_canonical_codebook(&codebook), &freqs);
+    let json = entries_to_json(&canonical);
+    assert_eq!(json.lines().count(), 7);
+    assert!(json.contains("{\"symbol\": 97, \"frequency\": 5, \"code\": \"0\", \"length\": 1}"));
+    assert_eq!(entries_to_json(&[]), "[]\n");
+}
+
+#[test]
+fn inspect_huffman_tables() {
+    let message = include_bytes!("../README.md");
+    for (spec, tables) in [("huffman:tables=1", 1), ("mtf:alphabet=all|huffman:tables=3", 3), ("rle1", 0)] {
+        let blob = write_diropqlz_with(message, &spec.parse().unwrap()).unwrap();
+        let reports = inspect_huffman(&blob).unwrap();
+        assert_eq!(reports.iter().map(|report| report.tables.len()).sum::<usize>(), tables, "{}", spec);
+
+        for table in reports.iter().flat_map(|report| &report.tables) {
+            // A complete prefix code, and every coded byte is in it
+            let kraft: f64 = table.iter().map(|entry| 0.5f64.powi(entry.code.len() as i32)).sum();
+            assert!((kraft - 1.0).abs() < 1e-12);
+            assert!(table.iter().all(|entry| entry.code.len() <= MAX_CODE_LEN as usize));
+        }
+    }
+    let blob = write_diropqlz_with(b"aaab", &"huffman:tables=1".parse().unwrap()).unwrap();
+    let table = &inspect_huffman(&blob).unwrap()[0].tables[0];
+    assert_eq!(table.iter().map(|entry| (entry.symbol, entry.frequency)).collect::<Vec<_>>(), vec![(b'a', 3), (b'b', 1)]);
+}
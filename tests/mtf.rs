@@ -0,0 +1,174 @@
+use message_obfuscation::compressor::bwt::bwt_encode_bytes;
+use message_obfuscation::compressor::mtf::{
+    byte_alphabet, input_alphabet, inversion_frequencies_decode, inversion_frequencies_encode, mtf_decode, mtf_decode_bytes,
+    mtf_encode, mtf_encode_bytes, rank_decode, rank_encode, MtfTable, MtfVariant, MAX_FREE_FILL,
+};
+use message_obfuscation::compressor::rle::rle_encode;
+use message_obfuscation::diropql::zip::{read_diropqlz_bytes, write_diropql_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::{write_varint, Mtf, Pipeline, Stage};
+use message_obfuscation::Error;
+
+mod common;
+
+use common::noise;
+
+#[test]
+fn symbols_outside_the_alphabet_are_errors() {
+    assert_eq!(mtf_encode("dix", "diropql"), Err(Error::UnknownSymbol('x')));
+    assert_eq!(mtf_encode_bytes(b"dix", b"diropql"), Err(Error::UnknownSymbol('x')));
+    assert_eq!(mtf_encode_bytes(&[0xe9], b"diropql"), Err(Error::UnknownSymbol('\u{e9}')));
+    assert!(mtf_decode_bytes(&[7], b"diropql").is_err());
+
+    // A fixed alphabet in a pipeline fails the same way instead of coding garbage
+    let pipeline: Pipeline = "mtf:alphabet=abc".parse().unwrap();
+    assert_eq!(write_diropqlz_with(b"abcd", &pipeline), Err(Error::UnknownSymbol('d')));
+}
+
+#[test]
+fn bad_alphabets_are_errors() {
+    let too_long: String = (0..257u32).map(|i| char::from_u32(0x100 + i).unwrap()).collect();
+    assert_eq!(mtf_encode("", &too_long), Err(Error::InvalidAlphabet("more than 256 symbols")));
+    assert_eq!(MtfTable::new(&[0; 257]).err(), Some(Error::InvalidAlphabet("more than 256 symbols")));
+    assert_eq!(MtfTable::new(b"abca").err(), Some(Error::InvalidAlphabet("repeated symbol")));
+    assert_eq!(mtf_encode_bytes(b"a", b"aa"), Err(Error::InvalidAlphabet("repeated symbol")));
+    assert!("mtf:alphabet=aa".parse::<Pipeline>().is_err());
+
+    assert_eq!(MtfTable::new(&byte_alphabet()).unwrap().symbols().len(), 256);
+    assert_eq!(input_alphabet(b"banana"), b"abn");
+}
+
+#[test]
+fn byte_alphabets_round_trip() {
+    let data = noise(5000, 21);
+    for alphabet in [byte_alphabet(), input_alphabet(&data)] {
+        let encoded = mtf_encode_bytes(&data, &alphabet).unwrap();
+        assert_eq!(mtf_decode_bytes(&encoded, &alphabet).unwrap(), data);
+    }
+
+    for spec in ["bwt|mtf:alphabet=all|rle", "bwt|mtf:alphabet=auto|rle", "mtf:alphabet=auto"] {
+        let pipeline: Pipeline = spec.parse().unwrap();
+        for data in [&data[..], b"", &[255; 3]] {
+            let blob = write_diropqlz_with(data, &pipeline).unwrap();
+            assert_eq!(read_diropqlz_bytes(&blob).unwrap(), data, "{}", spec);
+        }
+    }
+}
+
+#[test]
+fn variants_round_trip() {
+    let (bwt, _) = bwt_encode_bytes(include_bytes!("../README.md"));
+    let inputs = [bwt, noise(5000, 22), vec![b'a'; 300], b"abababbbbaaab".to_vec(), Vec::new()];
+
+    for variant in MtfVariant::ALL {
+        assert_eq!(MtfVariant::from_name(variant.name()), Some(variant));
+        for data in &inputs {
+            let alphabet = byte_alphabet();
+            let encoded = rank_encode(data, &alphabet, variant).unwrap();
+            assert_eq!(&rank_decode(&encoded, &alphabet, variant).unwrap(), data, "{}", variant.name());
+        }
+        assert_eq!(rank_encode(b"x", b"abc", variant), Err(Error::UnknownSymbol('x')));
+
+        // Inversion frequencies have no list and so no alphabet
+        let alphabet = if variant == MtfVariant::InversionFrequencies { "" } else { ",alphabet=all" };
+        let pipeline: Pipeline = format!("bwt|mtf:variant={}{}|rle|huffman", variant.name(), alphabet).parse().unwrap();
+        let blob = write_diropqlz_with(&inputs[1], &pipeline).unwrap();
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), inputs[1], "{}", variant.name());
+    }
+
+    // The variants only differ in where a coded symbol goes
+    assert_eq!(rank_encode(b"abba", b"ab", MtfVariant::MoveToFront).unwrap(), [0, 1, 0, 1]);
+    assert_eq!(rank_encode(b"bcc", b"abc", MtfVariant::Mtf1).unwrap(), [1, 2, 1]);
+    // On a BWT they all rank better than the raw bytes would
+    let (bwt, _) = bwt_encode_bytes(include_bytes!("../README.md"));
+    for variant in MtfVariant::ALL[..5].iter() {
+        let ranks = rank_encode(&bwt, &byte_alphabet(), *variant).unwrap();
+        assert!(rle_encode(&ranks).len() < bwt.len(), "{}", variant.name());
+    }
+}
+
+#[test]
+fn inversion_frequencies_round_trip() {
+    let (bwt, _) = bwt_encode_bytes(include_bytes!("../README.md"));
+    for data in [bwt, noise(5000, 23), vec![7; 100], b"x".to_vec(), Vec::new(), (0..=255).rev().collect()] {
+        assert_eq!(inversion_frequencies_decode(&inversion_frequencies_encode(&data)).unwrap(), data);
+    }
+    // Three bytes, one 0 with no larger byte before it, then 1 fills the rest
+    assert_eq!(inversion_frequencies_encode(&[0, 1, 1]), [3, 1, 0, 2]);
+}
+
+#[test]
+fn inversion_frequencies_reject_bad_counts() {
+    let encoded = inversion_frequencies_encode(&noise(1000, 24));
+    let malformed = Err(Error::InvalidPayload("malformed inversion frequencies"));
+    assert_eq!(inversion_frequencies_decode(&encoded[..encoded.len() - 1]), malformed);
+    assert_eq!(inversion_frequencies_decode(&[encoded.clone(), vec![0]].concat()), malformed);
+
+    for bad in [&[][..], &[3], &[3, 5], &[3, 1, 3, 2], &[3, 2, 0], &[2, 1, 0, 0, 0], &[0x80]] {
+        assert_eq!(inversion_frequencies_decode(bad), malformed, "{:?}", bad);
+    }
+}
+
+#[test]
+fn inversion_frequencies_length_is_bounded() {
+    // Nothing gets allocated for a length the data cannot hold
+    let mut header = Vec::new();
+    write_varint(&mut header, (MAX_FREE_FILL + 5) as u64);
+    let too_long = [header.clone(), vec![0x80, 0x80, 0x80, 0x08]].concat();
+    assert_eq!(inversion_frequencies_decode(&too_long), Err(Error::InvalidHeader("inversion frequencies too long")));
+    let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 0];
+    assert_eq!(inversion_frequencies_decode(&huge), Err(Error::InvalidHeader("inversion frequencies too long")));
+
+    // The largest byte fills up to the limit for free
+    let mut fill = Vec::new();
+    write_varint(&mut fill, MAX_FREE_FILL as u64);
+    let decoded = inversion_frequencies_decode(&[fill.clone(), fill].concat()).unwrap();
+    assert!(decoded.len() == MAX_FREE_FILL && decoded.iter().all(|&byte| byte == 0));
+
+    // The stage will not write more than that
+    let stage = Mtf { variant: MtfVariant::InversionFrequencies, ..Mtf::default() };
+    assert!(stage.encode(&vec![9; MAX_FREE_FILL + 1]).is_err());
+    assert!(stage.encode(&[vec![1; MAX_FREE_FILL + 1], vec![10]].concat()).is_ok());
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+#[test]
+fn byte_functions_match_the_string_ones() {
+    // BWTs of diropql programs, random programs and random strings of diropql commands
+    let mut inputs: Vec<(Vec<u8>, &[u8])> = Vec::new();
+    for message in [&include_bytes!("../README.md")[..3000], &noise(300, 25), b"a"] {
+        let program = write_diropql_bytes(message).into_bytes();
+        inputs.push((bwt_encode_bytes(&program).0, b"\0diropql"));
+        inputs.push((program, b"diropql"));
+    }
+    inputs.push((noise(5000, 26).into_iter().map(|byte| b"diropql"[byte as usize % 7]).collect(), b"diropql"));
+    inputs.push((noise(5000, 27), &[]));
+
+    for (data, alphabet) in inputs {
+        let alphabet = if alphabet.is_empty() { byte_alphabet() } else { alphabet.to_vec() };
+        let (text, alphabet_text) = (latin1(&data), latin1(&alphabet));
+
+        let encoded = mtf_encode_bytes(&data, &alphabet).unwrap();
+        assert_eq!(encoded, mtf_encode(&text, &alphabet_text).unwrap());
+        assert_eq!(mtf_decode_bytes(&encoded, &alphabet).unwrap(), data);
+        assert_eq!(mtf_decode(&encoded, &alphabet_text).unwrap(), text);
+    }
+}
+
+#[test]
+fn table_tracks_positions() {
+    // The decoder's table keeps no positions, but has to answer the same
+    for mut table in [MtfTable::new(b"diropql").unwrap(), MtfTable::for_decoding(b"diropql").unwrap()] {
+        assert_eq!((table.index_of(b'q'), table.index_of(b'x')), (Some(5), None));
+        table.move_to(5, 0);
+        table.move_to(6, 2);
+        assert_eq!(table.symbols(), b"qdlirop");
+        for (index, &byte) in table.symbols().iter().enumerate() {
+            assert_eq!(table.index_of(byte), Some(index));
+            assert_eq!(table.get(index), Some(byte));
+        }
+        assert_eq!(table.get(7), None);
+    }
+}
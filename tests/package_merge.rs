@@ -0,0 +1,70 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use message_obfuscation::compressor::huffman::limited_code_lengths;
+
+mod common;
+
+use common::{fibonacci, noise};
+
+// Cost of an unlimited Huffman code, the sum of every merged weight
+fn huffman_cost(freqs: &[u32]) -> u64 {
+    let mut heap: BinaryHeap<Reverse<u64>> = freqs.iter().filter(|&&f| f > 0).map(|&f| Reverse(f as u64)).collect();
+    if heap.len() == 1 {
+        return heap.pop().unwrap().0;
+    }
+    let mut cost = 0;
+    while heap.len() > 1 {
+        let merged = heap.pop().unwrap().0 + heap.pop().unwrap().0;
+        cost += merged;
+        heap.push(Reverse(merged));
+    }
+    cost
+}
+
+fn cost(freqs: &[u32], lengths: &[u8]) -> u64 {
+    freqs.iter().zip(lengths).map(|(&f, &length)| f as u64 * length as u64).sum()
+}
+
+#[test]
+fn lengths_respect_the_limit() {
+    let freqs = fibonacci(30);
+    assert_eq!(limited_code_lengths(&freqs, 63).unwrap().iter().max(), Some(&29));
+
+    for max_len in 5..=20 {
+        let lengths = limited_code_lengths(&freqs, max_len).unwrap();
+        assert!(lengths.iter().all(|&length| (1..=max_len).contains(&length)));
+        let kraft: f64 = lengths.iter().map(|&length| 0.5f64.powi(length as i32)).sum();
+        assert!((kraft - 1.0).abs() < 1e-12, "{}", max_len);
+    }
+    assert!(limited_code_lengths(&freqs, 4).is_none());
+}
+
+#[test]
+fn lengths_for_few_and_many_symbols() {
+    assert_eq!(limited_code_lengths(&[0, 0, 0], 8).unwrap(), vec![0, 0, 0]);
+    assert_eq!(limited_code_lengths(&[0, 9, 0], 8).unwrap(), vec![0, 1, 0]);
+    assert_eq!(limited_code_lengths(&[3, 0, 1], 8).unwrap(), vec![1, 0, 1]);
+
+    let lengths = limited_code_lengths(&[1; 258], 17).unwrap();
+    assert!(lengths.iter().all(|&length| length == 8 || length == 9));
+    assert!(limited_code_lengths(&[1; 258], 8).is_none());
+}
+
+#[test]
+fn lengths_are_optimal() {
+    let skewed: Vec<u32> = noise(256, 7).iter().map(|&byte| (byte as u32 % 17).pow(3)).collect();
+    for freqs in [fibonacci(30), fibonacci(12), skewed, vec![5, 1, 1, 1, 1], vec![1; 300]] {
+        // With room to spare the limit changes nothing
+        assert_eq!(cost(&freqs, &limited_code_lengths(&freqs, 63).unwrap()), huffman_cost(&freqs));
+
+        // A tighter limit never makes the code shorter
+        let mut previous = u64::MAX;
+        for max_len in (1..=30).rev() {
+            let Some(lengths) = limited_code_lengths(&freqs, max_len) else { break };
+            let current = cost(&freqs, &lengths);
+            assert!(previous == u64::MAX || current >= previous, "{}", max_len);
+            previous = current;
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use message_obfuscation::diropql::zip::{
+    read_diropql_bytes, read_diropqlz, read_diropqlz_bytes, write_diropql_bytes, write_diropqlz, write_diropqlz_with,
+    MEMORY_SIZE,
+};
+use message_obfuscation::pipeline::{
+    Armor, Base85, Bwt, Diropql, Huffman, Mtf, MtfAlphabet, Pipeline, PipelineBuilder, Rle,
+};
+use message_obfuscation::Error;
+
+fn mtf_bytes() -> Mtf {
+    Mtf { alphabet: MtfAlphabet::Bytes, ..Mtf::default() }
+}
+
+fn bwt(block_size: usize) -> Bwt {
+    Bwt { block_size, ..Bwt::default() }
+}
+
+fn names(pipeline: &Pipeline) -> Vec<&'static str> {
+    pipeline.stages().iter().map(|stage| stage.name()).collect()
+}
+
+#[test]
+fn reordered_pipelines_round_trip() {
+    let message = &include_bytes!("../README.md")[..2000];
+    let pipelines: Vec<PipelineBuilder> = vec![
+        Pipeline::builder(),
+        Pipeline::builder().stage(Rle),
+        Pipeline::builder().stage(mtf_bytes()).stage(bwt(100)),
+        Pipeline::builder().stage(bwt(300)).stage(mtf_bytes()).stage(Rle).stage(Huffman::default()),
+        Pipeline::builder().stage(Diropql).stage(Rle).stage(bwt(1000)).stage(mtf_bytes()),
+        Pipeline::builder().stage(Diropql).stage(Mtf::default()).stage(Huffman { tables: 1 }).stage(Base85),
+        Pipeline::builder().stage(Rle).stage(Rle).stage(Base85).stage(Base85),
+    ];
+
+    for builder in pipelines {
+        let pipeline = builder.build();
+        pipeline.validate().unwrap();
+        let (encoded, params) = pipeline.encode(message).unwrap();
+
+        // The header alone is enough to rebuild the stages
+        let mut header = Vec::new();
+        pipeline.write_header(&params, &mut header);
+        let (read, read_params) = Pipeline::read_header(&header, &mut 0).unwrap();
+        assert_eq!((names(&read), &read_params), (names(&pipeline), &params));
+        assert_eq!(read.decode(&encoded, &read_params).unwrap(), message, "{:?}", pipeline);
+
+        let blob = write_diropqlz_with(message, &pipeline).unwrap();
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message, "{:?}", pipeline);
+    }
+}
+
+#[test]
+fn messages_longer_than_memory() {
+    // Past MEMORY_SIZE bytes the program reuses cells, which must not leak into later bytes
+    let text = include_str!("../README.md").repeat(3)[..20_400].to_string();
+    let mut zeros_between = text.as_bytes().to_vec();
+    zeros_between[10..MEMORY_SIZE + 20].iter_mut().step_by(3).for_each(|byte| *byte = 0);
+    for message in [text.as_bytes(), &zeros_between] {
+        assert_eq!(read_diropql_bytes(&write_diropql_bytes(message)).unwrap(), message);
+        let blob = write_diropqlz_with(message, &"diropql|rle".parse().unwrap()).unwrap();
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message);
+    }
+    assert_eq!(read_diropqlz(&write_diropqlz(&text)).unwrap(), text);
+
+    // Shorter programs never need to clear a cell
+    assert!(!write_diropql_bytes(&text.as_bytes()[..MEMORY_SIZE]).contains('p'));
+}
+
+#[test]
+fn validate_rejects_bad_orders() {
+    let bad = [
+        Pipeline::builder().stage(Huffman::default()).stage(Rle).build(),
+        Pipeline::builder().stage(bwt(100)).stage(Huffman::default()).stage(mtf_bytes()).build(),
+        Pipeline::builder().stage(Rle).stage(Diropql).build(),
+    ];
+    for pipeline in bad {
+        assert!(matches!(pipeline.validate(), Err(Error::InvalidPipeline(_))), "{:?}", pipeline);
+    }
+    assert!(matches!("huffman|rle".parse::<Pipeline>(), Err(Error::InvalidPipeline(_))));
+
+    // Only armor may follow an entropy coder
+    Pipeline::builder().stage(Huffman::default()).stage(Base85).build().validate().unwrap();
+}
+
+#[test]
+fn bad_headers_are_errors() {
+    assert_eq!(Pipeline::read_header(&[1, 1, 200, 0], &mut 0).err(), Some(Error::InvalidHeader("unknown stage")));
+    assert_eq!(Pipeline::read_header(&[2, 0], &mut 0).err(), Some(Error::InvalidHeader("unsupported version")));
+    assert_eq!(Pipeline::read_header(&[1, 2, 4, 0], &mut 0).err(), Some(Error::InvalidHeader("truncated stage list")));
+    assert_eq!(Pipeline::read_header(&[1, 1, 4, 5, 0], &mut 0).err(), Some(Error::InvalidHeader("truncated stage parameters")));
+
+    // A whole blob whose header names a stage that does not exist
+    let blob = Armor::Base85.magic() + &Armor::Base85.encode(&[1, 1, 200, 0, 0, 0]);
+    assert_eq!(read_diropqlz_bytes(&blob), Err(Error::InvalidHeader("unknown stage")));
+}
+
+#[test]
+fn extra_bwt_indices_are_errors() {
+    let pipeline = Pipeline::builder().stage(bwt(100)).build();
+    let (encoded, mut params) = pipeline.encode(&[7; 250]).unwrap();
+    assert_eq!(pipeline.decode(&encoded, &params).unwrap(), [7; 250]);
+
+    params[0].push(0);
+    assert_eq!(pipeline.decode(&encoded, &params), Err(Error::InvalidHeader("more BWT indices than blocks")));
+    params[0].pop();
+    params[0].pop();
+    assert_eq!(pipeline.decode(&encoded, &params), Err(Error::InvalidHeader("missing BWT index")));
+}
@@ -0,0 +1,52 @@
+use message_obfuscation::compressor::range_coder::{range_decode, range_encode};
+use message_obfuscation::diropql::zip::{read_diropqlz_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::Pipeline;
+
+mod common;
+
+#[test]
+fn round_trip() {
+    for order in 0..=1 {
+        for (name, data) in common::samples().into_iter().chain([("ones", vec![0xff; 100_000])]) {
+            let encoded = range_encode(&data, order);
+            assert_eq!(range_decode(&encoded, data.len(), order).unwrap(), data, "{} order {}", name, order);
+        }
+    }
+}
+
+#[test]
+fn order1_learns_pairs() {
+    // Every byte follows from the one before it, which only order 1 can see
+    let data: Vec<u8> = (0..50_000u32).map(|i| (i * 37 % 256) as u8).collect();
+    let (order0, order1) = (range_encode(&data, 0).len(), range_encode(&data, 1).len());
+    assert!(order1 * 4 < order0, "order 0: {}, order 1: {}", order0, order1);
+}
+
+#[test]
+fn long_one_sided_input() {
+    // The mixer keeps pushing the same weights the same way, they must level off rather than overflow
+    for data in [vec![0; 1_000_000], (0..1_000_000u32).map(|i| (i & 1) as u8 * 0xff).collect()] {
+        let encoded = range_encode(&data, 1);
+        assert!(encoded.len() < data.len() / 50, "{}", encoded.len());
+        assert_eq!(range_decode(&encoded, data.len(), 1).unwrap(), data);
+    }
+}
+
+#[test]
+fn rejects_bad_data() {
+    let encoded = range_encode(include_bytes!("../README.md"), 1);
+    assert!(range_decode(&encoded[..encoded.len() / 2], 8913, 1).is_err());
+    assert!(range_decode(&[1, 0, 0, 0, 0], 1, 0).is_err());
+    assert!(range_decode(&[0, 0, 0], 1, 0).is_err());
+}
+
+#[test]
+fn stage_round_trip() {
+    let message = include_bytes!("../README.md");
+    for spec in ["range", "range:order=0", "diropql|bwt|mtf|rle|range", "bwt|mtf:alphabet=all|rle|range:order=0"] {
+        let pipeline: Pipeline = spec.parse().unwrap();
+        let blob = write_diropqlz_with(message, &pipeline).unwrap();
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message, "{}", spec);
+    }
+    assert!("range:order=2".parse::<Pipeline>().is_err());
+}
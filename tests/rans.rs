@@ -0,0 +1,109 @@
+use message_obfuscation::compressor::rans::{
+    normalize_frequencies, rans_decode, rans_encode, INTERLEAVE, MAX_FREE_COUNT, SCALE_BITS,
+};
+use message_obfuscation::diropql::zip::{read_diropqlz_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::Pipeline;
+use message_obfuscation::Error;
+
+fn frequencies(data: &[u8]) -> Vec<u32> {
+    let mut counts = [0u32; 256];
+    data.iter().for_each(|&byte| counts[byte as usize] += 1);
+    normalize_frequencies(&counts)
+}
+
+#[test]
+fn frequencies_are_normalised() {
+    let mut counts = vec![1u32; 256];
+    counts[b'e' as usize] = 1_000_000;
+    for counts in [counts, vec![0, 3, 0, 1], vec![7], (1..=256).collect()] {
+        let freqs = normalize_frequencies(&counts);
+        assert_eq!(freqs.iter().sum::<u32>(), 1 << SCALE_BITS);
+        assert!(counts.iter().zip(&freqs).all(|(&count, &freq)| (count == 0) == (freq == 0)));
+    }
+    assert_eq!(normalize_frequencies(&[0, 0]), vec![0, 0]);
+}
+
+#[test]
+fn round_trip() {
+    let text = include_bytes!("../README.md");
+    let samples: [&[u8]; 4] = [b"a", &[b'z'; 10_000], &(0..=255).collect::<Vec<u8>>(), text];
+    for data in samples {
+        for ways in [1, INTERLEAVE] {
+            let freqs = frequencies(data);
+            let encoded = rans_encode(data, &freqs, ways);
+            assert_eq!(rans_decode(&encoded, &freqs, data.len(), ways).unwrap(), data, "{} ways", ways);
+        }
+    }
+
+    // A byte that owns every slot leaves only the final states
+    assert_eq!(rans_encode(&[b'z'; 10_000], &frequencies(b"z"), 1).len(), 4);
+}
+
+#[test]
+fn output_is_fixed() {
+    // The format has to decode the same everywhere, so pin it down
+    let data = b"abracadabra";
+    let freqs = frequencies(data);
+    assert_eq!(freqs[b'a' as usize..=b'r' as usize].iter().filter(|&&freq| freq > 0).count(), 5);
+    assert_eq!(rans_encode(data, &freqs, 1), vec![43, 136, 38, 138, 126, 32]);
+}
+
+#[test]
+fn rejects_bad_data() {
+    let text = include_bytes!("../README.md");
+    let freqs = frequencies(text);
+    let encoded = rans_encode(text, &freqs, INTERLEAVE);
+
+    assert!(rans_decode(&encoded[..encoded.len() - 1], &freqs, text.len(), INTERLEAVE).is_err());
+    assert!(rans_decode(&encoded[..10], &freqs, text.len(), INTERLEAVE).is_err());
+    assert!(rans_decode(&encoded, &freqs, text.len(), 1).is_err());
+    let mut corrupt = encoded.clone();
+    corrupt[100] ^= 0x10;
+    assert!(rans_decode(&corrupt, &freqs, text.len(), INTERLEAVE).is_err());
+    assert!(rans_decode(&encoded, &[1 << SCALE_BITS, 1], text.len(), INTERLEAVE).is_err());
+}
+
+#[test]
+fn count_is_bounded() {
+    // Nothing gets allocated for a count the data cannot hold
+    let text = include_bytes!("../README.md");
+    let freqs = frequencies(text);
+    let encoded = rans_encode(text, &freqs, INTERLEAVE);
+    let too_large = Err(Error::InvalidHeader("rANS symbol count too large"));
+    assert_eq!(rans_decode(&encoded, &freqs, usize::MAX, INTERLEAVE), too_large);
+    assert_eq!(rans_decode(&encoded, &freqs, encoded.len() * 8 * 4096, INTERLEAVE), too_large);
+
+    let lone = frequencies(b"z");
+    let states = rans_encode(b"z", &lone, 1);
+    assert_eq!(rans_decode(&states, &lone, MAX_FREE_COUNT + 1, 1), too_large);
+    assert!(rans_decode(&states, &lone, 1 << 20, 1).unwrap().iter().all(|&byte| byte == b'z'));
+
+    // The bound holds even for the cheapest bytes a table can have
+    let mut data = vec![b'a'; 2_000_000];
+    data[1000] = b'b';
+    let freqs = frequencies(&data);
+    assert_eq!(freqs[b'a' as usize], (1 << SCALE_BITS) - 1);
+    let encoded = rans_encode(&data, &freqs, 1);
+    assert_eq!(rans_decode(&encoded, &freqs, data.len(), 1).unwrap(), data);
+
+    // Past the free count, the stage gives a lone byte a neighbour so its blob still decodes
+    let data = vec![0xff; MAX_FREE_COUNT + 1];
+    let blob = write_diropqlz_with(&data, &"rans".parse().unwrap()).unwrap();
+    assert!(blob.len() < 1000);
+    assert_eq!(read_diropqlz_bytes(&blob).unwrap(), data);
+}
+
+#[test]
+fn stage_round_trip() {
+    let text = include_bytes!("../README.md");
+    for spec in ["diropql|bwt:block=256k|mtf|rle|rans", "diropql|bwt:block=256k|mtf|rle|rans:interleave=false", "rans"] {
+        let pipeline: Pipeline = spec.parse().unwrap();
+        assert_eq!(pipeline.to_string(), format!("{}|base85", spec));
+
+        for data in [&text[..], b"x", b""] {
+            let blob = write_diropqlz_with(data, &pipeline).unwrap();
+            assert_eq!(read_diropqlz_bytes(&blob).unwrap(), data, "{}", spec);
+        }
+    }
+    assert!("rans:interleave=2".parse::<Pipeline>().is_err());
+}
@@ -0,0 +1,140 @@
+use message_obfuscation::compressor::rle::{
+    command_runs_decode, command_runs_encode, packbits_decode, packbits_encode, rle1_decode, rle1_encode, rle_decode,
+    rle_encode, MAX_DECODED_LEN,
+};
+use message_obfuscation::diropql::zip::{read_diropqlz_bytes, write_diropql_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::Pipeline;
+use message_obfuscation::Error;
+
+mod common;
+
+use common::{noise, runs};
+
+fn samples() -> Vec<(&'static str, Vec<u8>)> {
+    let mut samples = common::samples();
+    samples.extend([
+        ("high bytes", vec![254, 255, 255, 254, 0, 255, 128, 128, 128, 255]),
+        ("runs", runs(&[1, 2, 3, 4, 5, 127, 128, 129, 255, 256, 259, 1000, 2, 1])),
+        ("sparse", noise(20_000, 3).into_iter().map(|b| if b < 230 { 0 } else { b }).collect()),
+        ("program", write_diropql_bytes(b"Hello, diropql!").into_bytes()),
+    ]);
+    samples
+}
+
+#[test]
+fn zero_runs_round_trip() {
+    for (name, data) in samples() {
+        assert_eq!(rle_decode(&rle_encode(&data)).unwrap(), data, "{}", name);
+    }
+}
+
+#[test]
+fn zero_runs_past_255() {
+    // Runs used to be counted in a byte and wrapped after 255 zeros
+    for len in [254usize, 255, 256, 257, 511, 1000, 65_535, 65_536, 1 << 20] {
+        let mut data = vec![7];
+        data.extend(std::iter::repeat_n(0, len));
+        data.push(255);
+        let encoded = rle_encode(&data);
+        // Bijective base 2 needs the bit length of len + 1, less one, digits
+        assert_eq!(encoded.len(), 2 + (usize::BITS - (len + 1).leading_zeros() - 1) as usize, "{} zeros", len);
+        assert_eq!(rle_decode(&encoded).unwrap(), data, "{} zeros", len);
+    }
+    assert_eq!(rle_encode(&[0; 256]), vec![1, 0, 0, 0, 0, 0, 0, 0]);
+
+    let pipeline: Pipeline = "rle|huffman".parse().unwrap();
+    let message: Vec<u8> = [vec![0; 300], vec![254, 255, 0, 255], vec![0; 70_000], vec![254]].concat();
+    let blob = write_diropqlz_with(&message, &pipeline).unwrap();
+    assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message);
+}
+
+#[test]
+fn decoded_length_is_bounded() {
+    // Digits of a zero run, as rle_encode writes them
+    let run = |len: usize| {
+        let mut digits = Vec::new();
+        let mut n = len + 1;
+        while n > 1 {
+            digits.push((n & 1) as u16);
+            n >>= 1;
+        }
+        digits
+    };
+    let too_long = Err(Error::InvalidPayload("RLE zero run too long"));
+    assert_eq!(rle_decode(&run(MAX_DECODED_LEN + 1)), too_long);
+    assert_eq!(rle_decode(&[vec![9], run(MAX_DECODED_LEN)].concat()), too_long);
+    assert_eq!(rle_decode(&[vec![9], run(1 << 20)].concat()).unwrap().len(), (1 << 20) + 1);
+}
+
+#[test]
+fn zero_runs_keep_high_bytes_apart() {
+    let encoded = rle_encode(&[0, 254, 255, 0, 0]);
+    assert_eq!(encoded, vec![0, 256, 257, 1]);
+    assert!(rle_decode(&[2]).is_err());
+    assert!(rle_decode(&[258]).is_err());
+    assert!(rle_decode(&[1; 80]).is_err());
+}
+
+#[test]
+fn packbits_round_trip() {
+    for (name, data) in samples() {
+        assert_eq!(packbits_decode(&packbits_encode(&data)).unwrap(), data, "{}", name);
+    }
+}
+
+#[test]
+fn packbits_packets() {
+    assert_eq!(packbits_encode(b"aaaab"), vec![253, b'a', 0, b'b']);
+    assert_eq!(packbits_encode(b"abc"), vec![2, b'a', b'b', b'c']);
+    assert_eq!(packbits_decode(&[128, 255, b'z']).unwrap(), b"zz");
+    assert!(packbits_decode(&[3, b'a']).is_err());
+    assert!(packbits_decode(&[200]).is_err());
+}
+
+#[test]
+fn rle1_round_trip() {
+    for (name, data) in samples() {
+        assert_eq!(rle1_decode(&rle1_encode(&data)), data, "{}", name);
+    }
+}
+
+#[test]
+fn rle1_counts() {
+    assert_eq!(rle1_encode(b"aaab"), b"aaab");
+    assert_eq!(rle1_encode(b"aaaa"), vec![b'a', b'a', b'a', b'a', 0]);
+    assert_eq!(rle1_encode(&[7; 300]), vec![7, 7, 7, 7, 251, 7, 7, 7, 7, 41]);
+}
+
+#[test]
+fn command_runs_round_trip() {
+    for (name, data) in samples() {
+        assert_eq!(command_runs_decode(&command_runs_encode(&data)).unwrap(), data, "{}", name);
+    }
+}
+
+#[test]
+fn command_runs_shrink_programs() {
+    let program = write_diropql_bytes(b"A");
+    assert_eq!(command_runs_encode(program.as_bytes()), vec![b'r', b'i', 0x80 + 63, b'o', b'r']);
+
+    let program = write_diropql_bytes(&[200]);
+    assert_eq!(command_runs_encode(program.as_bytes()), vec![b'r', b'i', 0xfe, 0x80 + 71, b'o', b'r']);
+
+    assert!(command_runs_decode(&[0x90]).is_err());
+    assert!(command_runs_decode(&[b'i', 0xff]).is_err());
+}
+
+#[test]
+fn stages_round_trip() {
+    let message = noise(3000, 11).into_iter().map(|b| b % 8 + b'a').collect::<Vec<u8>>();
+    for spec in [
+        "packbits|huffman",
+        "rle1|bwt|mtf:alphabet=all|rle",
+        "diropql|cmdrle|bwt|mtf:alphabet=auto|rle|huffman",
+        "diropql|rle1|packbits",
+    ] {
+        let pipeline: Pipeline = spec.parse().unwrap();
+        let blob = write_diropqlz_with(&message, &pipeline).unwrap();
+        assert_eq!(read_diropqlz_bytes(&blob).unwrap(), message, "{}", spec);
+    }
+}
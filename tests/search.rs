@@ -0,0 +1,78 @@
+use message_obfuscation::compressor::bwt::bwt_encode_bytes;
+use message_obfuscation::compressor::fm_index::FmIndex;
+use message_obfuscation::diropql::search::{search_diropqlz, search_diropqlz_program};
+use message_obfuscation::diropql::zip::{write_diropql_bytes, write_diropqlz_with};
+use message_obfuscation::pipeline::Pipeline;
+use message_obfuscation::Error;
+
+mod common;
+
+use common::noise;
+
+fn naive(data: &[u8], pattern: &[u8]) -> Vec<usize> {
+    (0..=data.len().saturating_sub(pattern.len()))
+        .filter(|&i| data[i..].starts_with(pattern))
+        .collect()
+}
+
+#[test]
+fn fm_index_matches_a_scan() {
+    let text = include_bytes!("../README.md")[..3000].to_vec();
+    let letters: Vec<u8> = noise(2000, 13).into_iter().map(|byte| b'a' + byte % 4).collect();
+
+    for data in [text, letters, b"aaaaaaaaaa".to_vec(), b"x".to_vec()] {
+        let (bwt, index) = bwt_encode_bytes(&data);
+        let n = data.len();
+        let mut patterns: Vec<Vec<u8>> = vec![data[..1].to_vec(), data[n / 2..n / 2 + 1].to_vec(), b"zzq".to_vec()];
+        patterns.extend((0..n.saturating_sub(8)).step_by(211).map(|i| data[i..i + 1 + i % 7].to_vec()));
+
+        for sample_rate in [1, 3, 32, n, n + 1, 10 * n] {
+            let fm_index = FmIndex::with_sample_rate(&bwt, index, b'e', sample_rate);
+            assert_eq!(fm_index.len(), n + 1);
+            assert_eq!(fm_index.separators(), data.iter().filter(|&&byte| byte == b'e').count());
+
+            for pattern in &patterns {
+                let expected = naive(&data, pattern);
+                assert_eq!(fm_index.count(pattern), expected.len(), "{:?} rate {}", pattern, sample_rate);
+
+                let mut hits = fm_index.locate(pattern);
+                hits.sort_by_key(|hit| hit.offset);
+                assert_eq!(hits.iter().map(|hit| hit.offset).collect::<Vec<_>>(), expected);
+                for hit in hits {
+                    assert_eq!(hit.separators, data[..hit.offset].iter().filter(|&&byte| byte == b'e').count());
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn blob_search_matches_a_scan() {
+    let message = &include_bytes!("../README.md")[..3000];
+    let program = write_diropql_bytes(message).into_bytes();
+    let blob = write_diropqlz_with(message, &Pipeline::default()).unwrap();
+
+    for pattern in [&b"the"[..], b"e", b"\n", b"diropql", b"Burrows-Wheeler", b"zzq"] {
+        let found = search_diropqlz(&blob, pattern).unwrap();
+        let mut offsets = found.offsets.clone();
+        offsets.sort();
+        assert_eq!(offsets, naive(message, pattern), "{:?}", String::from_utf8_lossy(pattern));
+        assert_eq!(found.len, message.len());
+    }
+
+    for pattern in [&b"rio"[..], b"o", b"ri"] {
+        let found = search_diropqlz_program(&blob, pattern).unwrap();
+        let mut offsets = found.offsets.clone();
+        offsets.sort();
+        assert_eq!(offsets, naive(&program, pattern));
+        assert_eq!(found.len, program.len());
+    }
+
+    // Without diropql there is no program to search, and the message still can be
+    let blob = write_diropqlz_with(message, &"bwt|mtf:alphabet=all|rle".parse().unwrap()).unwrap();
+    assert!(matches!(search_diropqlz_program(&blob, b"rio"), Err(Error::InvalidPipeline(_))));
+    assert_eq!(search_diropqlz(&blob, b"the").unwrap().offsets.len(), naive(message, b"the").len());
+
+    let blob = write_diropqlz_with(message, &"rle|bwt".parse().unwrap()).unwrap();
+    assert!(matches!(search_diropqlz(&blob, b"the"), Err(Error::InvalidPipeline(_))));
+}
@@ -0,0 +1,59 @@
+use std::io::{Read, Write};
+
+use message_obfuscation::diropql::stego::{hide, reveal, Carrier};
+use message_obfuscation::pipeline::{Armor, Pipeline};
+use message_obfuscation::{DiropqlzReader, DiropqlzWriter, Error};
+
+const COVER: &str = "Dear all,\n\nthe meeting moves to Thursday at ten.\nPlease bring your notes.\n\nThanks\n";
+
+fn blobs(message: &[u8], armor: Armor) -> String {
+    let mut writer = DiropqlzWriter::with_pipeline(Vec::new(), Pipeline::default().with_armor(armor), 1000);
+    writer.write_all(message).unwrap();
+    String::from_utf8(writer.finish().unwrap()).unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let message = &include_bytes!("../README.md")[..3000];
+    for carrier in Carrier::ALL {
+        assert_eq!(Carrier::from_name(carrier.name()), Some(carrier));
+        for armor in [Armor::Base85, Armor::Words, Armor::Base7 { wrap: 40 }] {
+            let payload = blobs(message, armor);
+            for cover in [COVER, "", "no-spaces-or-newline"] {
+                let text = hide(&payload, cover, carrier);
+                assert_eq!(reveal(&text).unwrap(), payload, "{} {}", carrier.name(), armor.name());
+
+                let mut decoded = Vec::new();
+                DiropqlzReader::new(reveal(&text).unwrap().as_bytes()).read_to_end(&mut decoded).unwrap();
+                assert_eq!(decoded, message);
+            }
+        }
+    }
+}
+
+#[test]
+fn cover_looks_unchanged() {
+    let payload = blobs(b"meet at noon", Armor::Base85);
+
+    let text = hide(&payload, COVER, Carrier::ZeroWidth);
+    assert_eq!(text.chars().filter(|c| !matches!(c, '\u{200b}' | '\u{200c}')).collect::<String>(), COVER);
+    assert_eq!(text.chars().count(), COVER.len() + payload.len() * 8);
+
+    // Every line carries a share, so none of them ends in a long tail
+    let text = hide(&payload, COVER, Carrier::Whitespace);
+    assert_eq!(text.lines().map(str::trim_end).collect::<Vec<_>>(), COVER.lines().collect::<Vec<_>>());
+    let longest = (payload.len() * 8).div_ceil(COVER.lines().count());
+    assert!(text.lines().all(|line| line.len() - line.trim_end().len() <= longest));
+
+    // Whatever the cover already hid is not mistaken for the payload
+    let cover = "  trailing \t\nzero\u{200b}width\n";
+    assert_eq!(reveal(&hide(&payload, cover, Carrier::Whitespace)).unwrap(), payload);
+    assert_eq!(reveal(&hide(&payload, cover, Carrier::ZeroWidth)).unwrap(), payload);
+}
+
+#[test]
+fn rejects_bad_covers() {
+    assert_eq!(reveal(COVER), Err(Error::InvalidCover("no hidden payload")));
+    assert_eq!(reveal("a\u{200b}\u{200c}b"), Err(Error::InvalidCover("hidden bits do not make whole bytes")));
+    assert_eq!(reveal("line\t\t\t\t\n\t\t\t\t\n"), Err(Error::InvalidCover("hidden payload is not text")));
+}
@@ -0,0 +1,58 @@
+use std::io::{ErrorKind, Read, Write};
+
+use message_obfuscation::diropql::stream::MAX_BLOCK_SIZE;
+use message_obfuscation::diropql::zip::write_diropqlz_with;
+use message_obfuscation::pipeline::Pipeline;
+use message_obfuscation::{DiropqlzReader, DiropqlzWriter};
+
+fn write_stream(data: &[u8], block_size: usize) -> Vec<u8> {
+    let mut writer = DiropqlzWriter::with_block_size(Vec::new(), block_size);
+    // Writes that do not line up with the blocks
+    for chunk in data.chunks(333) {
+        writer.write_all(chunk).unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn read_stream(stream: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    DiropqlzReader::new(stream).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+#[test]
+fn round_trip_across_blocks() {
+    let text = include_bytes!("../README.md").repeat(3);
+    for (block_size, len) in [(1, 20), (7, 50), (MAX_BLOCK_SIZE, 2 * MAX_BLOCK_SIZE + 123)] {
+        let data = &text[..len];
+        let stream = write_stream(data, block_size);
+        assert_eq!(stream.split(|&byte| byte == b'\n').filter(|line| !line.is_empty()).count(), len.div_ceil(block_size));
+        assert_eq!(read_stream(&stream).unwrap(), data, "blocks of {}", block_size);
+    }
+
+    // Blocks are capped, so a larger size still yields blobs that fit in memory
+    let data = &text[..MAX_BLOCK_SIZE + 1];
+    let stream = write_stream(data, usize::MAX);
+    assert_eq!(stream.iter().filter(|&&byte| byte == b'\n').count(), 2);
+    assert_eq!(read_stream(&stream).unwrap(), data);
+
+    // A whole blob written past the cap, outside the stream, reads back the same
+    let blob = write_diropqlz_with(data, &Pipeline::default()).unwrap() + "\n";
+    assert_eq!(read_stream(blob.as_bytes()).unwrap(), data);
+
+    assert!(write_stream(b"", 10).is_empty());
+    assert!(read_stream(b"").unwrap().is_empty());
+}
+
+#[test]
+fn corrupt_lines_are_errors() {
+    let stream = String::from_utf8(write_stream(b"first block, second block", 13)).unwrap();
+    let lines: Vec<&str> = stream.lines().collect();
+    assert_eq!(lines.len(), 2);
+
+    for bad in ["DIROPQLHzz", "not a blob", &lines[1][..lines[1].len() / 2]] {
+        let corrupt = format!("{}\n{}\n", lines[0], bad);
+        let error = read_stream(corrupt.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", bad);
+    }
+}